@@ -159,6 +159,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     )
     .await?;
 
+    eprintln!("Refreshing project similarity...");
+    project::refresh_similarity(&pool).await?;
+
     eprintln!("Done");
 
     Ok(())
@@ -110,6 +110,16 @@ pub async fn sync_deleted(pool: &PgPool, projects: &HashSet<String>) -> anyhow::
     Ok(tx.commit().await?)
 }
 
+/// Recomputes the neighbors cached in the `project_similarity` view. Refreshing
+/// concurrently keeps the view readable while it's being rebuilt.
+pub async fn refresh_similarity(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!("REFRESH MATERIALIZED VIEW CONCURRENTLY project_similarity")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
 pub async fn insert_many(pool: &PgPool, projects: Vec<Project>) -> anyhow::Result<()> {
     let mut tx = pool.begin().await?;
 
@@ -1,6 +1,9 @@
 use std::str::FromStr;
 
-use axum::http::HeaderMap;
+use axum::extract::State;
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
 use base58::{FromBase58, ToBase58};
 use ed25519_dalek::{PublicKey, Signature, Verifier};
 use near_account_id::AccountId;
@@ -173,6 +176,40 @@ pub async fn authorize(
     }
 }
 
+/// Header holding the key for the routes gated by [`require_api_key`].
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Rejects requests to the gated routes that don't carry one of the configured API
+/// keys. Other routes stay public, and nothing is gated when no keys are configured,
+/// which [`AppState::new`] only allows in debug builds.
+pub async fn require_api_key<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let gated = !state.api_keys.is_empty()
+        && state
+            .gated_routes
+            .iter()
+            .any(|route| route == request.uri().path());
+
+    if gated {
+        let key = request
+            .headers()
+            .get(API_KEY_HEADER)
+            .and_then(|key| key.to_str().ok());
+        match key {
+            None => return (StatusCode::UNAUTHORIZED, "Missing API key").into_response(),
+            Some(key) if !state.api_keys.contains(key) => {
+                return (StatusCode::UNAUTHORIZED, "Invalid API key").into_response()
+            }
+            _ => {}
+        }
+    }
+
+    next.run(request).await
+}
+
 pub fn encrypt_string(value: &str, key: &sodiumoxide::crypto::secretbox::Key) -> String {
     if value.is_empty() {
         return String::new();
@@ -209,3 +246,91 @@ pub async fn verify_signature(message: &str, signature: &str, public_key: &str)
     };
     public_key.verify(message.as_bytes(), &signature).is_ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use tokio::sync::Semaphore;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    /// The app state gating `/gated` behind `api_keys`. The pool never connects, the
+    /// routes under test don't query the database.
+    fn gated_state(api_keys: &[&str]) -> AppState {
+        AppState {
+            client: Client::new(),
+            contract_id: "nearhorizon.near".parse().unwrap(),
+            atlas_route: String::new(),
+            atlas_auth: String::new(),
+            key: sodiumoxide::crypto::secretbox::gen_key(),
+            pool: sqlx::postgres::PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/horizon")
+                .unwrap(),
+            similar_min_completion: crate::DEFAULT_SIMILAR_MIN_COMPLETION,
+            similarity_candidate_cap: crate::DEFAULT_SIMILARITY_CANDIDATE_CAP,
+            expensive_queries: Arc::new(Semaphore::new(1)),
+            expensive_query_timeout: Duration::from_secs(1),
+            default_sort: Default::default(),
+            query_timeout: Duration::from_secs(1),
+            api_keys: Arc::new(api_keys.iter().map(|key| key.to_string()).collect()),
+            gated_routes: Arc::new(vec!["/gated".to_string()]),
+        }
+    }
+
+    /// The status of a request to `path` through [`require_api_key`], with `key` in
+    /// the [`API_KEY_HEADER`] if provided.
+    async fn status(state: AppState, path: &str, key: Option<&str>) -> StatusCode {
+        let router = Router::new()
+            .route("/gated", get(|| async { "gated" }))
+            .route("/public", get(|| async { "public" }))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                require_api_key,
+            ))
+            .with_state(state);
+
+        let mut request = Request::builder().uri(path);
+        if let Some(key) = key {
+            request = request.header(API_KEY_HEADER, key);
+        }
+        router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_require_api_key() {
+        let state = gated_state(&["secret"]);
+
+        assert_eq!(
+            status(state.clone(), "/gated", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(state.clone(), "/gated", Some("wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(state.clone(), "/gated", Some("secret")).await,
+            StatusCode::OK
+        );
+        assert_eq!(status(state, "/public", None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_require_api_key_without_keys() {
+        // Debug builds start without keys, leaving the gated routes public
+        assert_eq!(
+            status(gated_state(&[]), "/gated", None).await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::routes::{
+    data::projects::{FullProject, Params},
+    transactions::Stats,
+};
+
+/// A typed client for the API, meant for other Rust services so they don't have to
+/// build the requests by hand.
+#[derive(Clone, Debug)]
+pub struct HorizonClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HorizonClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> reqwest::Result<T> {
+        self.client
+            .get(format!("{}{path}", self.base_url))
+            .query(query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    pub async fn list_projects(&self, params: &Params) -> reqwest::Result<Vec<String>> {
+        self.get("/data/projects", &project_query(params)).await
+    }
+
+    /// The project along with its similar projects and latest activity.
+    pub async fn get_project(&self, account_id: &str) -> reqwest::Result<FullProject> {
+        self.get(&format!("/data/projects/{account_id}/full"), &[])
+            .await
+    }
+
+    pub async fn similar(&self, account_id: &str) -> reqwest::Result<Vec<String>> {
+        self.get(&format!("/data/projects/{account_id}/similar"), &[])
+            .await
+    }
+
+    pub async fn stats(&self) -> reqwest::Result<Stats> {
+        self.get("/transactions/stats", &[]).await
+    }
+}
+
+/// Serializes a unit enum variant into the name the server deserializes it from.
+fn variant_name<T: Serialize>(value: T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Converts the listing params into the comma separated query the server expects.
+fn project_query(params: &Params) -> Vec<(&'static str, String)> {
+    let mut query = vec![("vertical_match", variant_name(params.vertical_match))];
+
+    if let Some(sort) = params.sort {
+        query.push(("sort", variant_name(sort)));
+    }
+
+    let sets = [
+        ("vertical", &params.vertical),
+        ("integration", &params.integration),
+        ("dev", &params.dev),
+        ("stage", &params.stage),
+        ("distribution", &params.distribution),
+        ("has", &params.has),
+        ("ids", &params.ids),
+    ];
+
+    query.extend(sets.into_iter().filter_map(|(name, set)| {
+        set.as_ref()
+            .map(|set| (name, set.iter().cloned().collect::<Vec<_>>().join(",")))
+    }));
+
+    if let Some(size) = &params.size {
+        query.push((
+            "size",
+            size.ranges
+                .iter()
+                .map(|(from, to)| format!("{from}-{to}"))
+                .collect::<Vec<_>>()
+                .join(","),
+        ));
+    }
+
+    if let Some(from) = params.from {
+        query.push(("from", from.to_string()));
+    }
+
+    if let Some(limit) = params.limit {
+        query.push(("limit", limit.to_string()));
+    }
+
+    if let Some(search) = &params.search {
+        query.push(("q", search.clone()));
+    }
+
+    if params.include_deleted {
+        query.push(("include_deleted", true.to_string()));
+    }
+
+    if params.strict {
+        query.push(("strict", true.to_string()));
+    }
+
+    query
+}
@@ -1,15 +1,51 @@
+use std::collections::HashSet;
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 use auth::{decrypt_string, encrypt_string};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
 use near_account_id::AccountId;
 use reqwest::Client;
+use routes::data::projects::Sort;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 pub mod auth;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod routes;
 
 pub const RPC_URL: &str = "https://rpc.mainnet.near.org";
 
+/// The completion a project needs to have to show up in similar projects, unless
+/// overridden by the `SIMILAR_MIN_COMPLETION` variable.
+pub const DEFAULT_SIMILAR_MIN_COMPLETION: f64 = 0.2;
+
+/// How many candidates similar projects are picked from at most, unless overridden
+/// by the `SIMILARITY_CANDIDATE_CAP` variable. The `project_similarity` view is built
+/// with this cap, so it's only used while the cap isn't overridden.
+pub const DEFAULT_SIMILARITY_CANDIDATE_CAP: i64 = 1_000;
+
+/// How many expensive queries can run at once, unless overridden by the
+/// `EXPENSIVE_QUERY_LIMIT` variable.
+pub const DEFAULT_EXPENSIVE_QUERY_LIMIT: usize = 3;
+
+/// How many seconds a request waits for an expensive query slot before giving up,
+/// unless overridden by the `EXPENSIVE_QUERY_TIMEOUT` variable.
+pub const DEFAULT_EXPENSIVE_QUERY_TIMEOUT: u64 = 10;
+
+/// How many seconds a heavy query can run for before it's cancelled, unless
+/// overridden by the `QUERY_TIMEOUT` variable.
+pub const DEFAULT_QUERY_TIMEOUT: u64 = 15;
+
+/// Comma separated routes that need an API key, unless overridden by the
+/// `GATED_ROUTES` variable. Keys are set with the `API_KEYS` variable, release builds
+/// refuse to start with gated routes but no keys.
+pub const DEFAULT_GATED_ROUTES: &str = "/data/projects/stream";
+
 #[derive(Serialize, Deserialize, Default, Debug)]
 pub struct PrivateGraduation {
     #[serde(default)]
@@ -82,6 +118,14 @@ pub struct AppState {
     pub atlas_auth: String,
     pub key: sodiumoxide::crypto::secretbox::Key,
     pub pool: sqlx::PgPool,
+    pub similar_min_completion: f64,
+    pub similarity_candidate_cap: i64,
+    pub expensive_queries: Arc<Semaphore>,
+    pub expensive_query_timeout: Duration,
+    pub default_sort: Sort,
+    pub query_timeout: Duration,
+    pub api_keys: Arc<HashSet<String>>,
+    pub gated_routes: Arc<Vec<String>>,
 }
 
 impl AppState {
@@ -114,6 +158,45 @@ impl AppState {
         #[cfg(debug_assertions)]
         let atlas_auth = "".to_string();
 
+        let similar_min_completion =
+            var_or("SIMILAR_MIN_COMPLETION", DEFAULT_SIMILAR_MIN_COMPLETION);
+
+        let similarity_candidate_cap =
+            var_or("SIMILARITY_CANDIDATE_CAP", DEFAULT_SIMILARITY_CANDIDATE_CAP);
+
+        let expensive_queries = Arc::new(Semaphore::new(var_or(
+            "EXPENSIVE_QUERY_LIMIT",
+            DEFAULT_EXPENSIVE_QUERY_LIMIT,
+        )));
+
+        let expensive_query_timeout = Duration::from_secs(var_or(
+            "EXPENSIVE_QUERY_TIMEOUT",
+            DEFAULT_EXPENSIVE_QUERY_TIMEOUT,
+        ));
+
+        let default_sort = var_or("DEFAULT_SORT", Sort::default());
+
+        let query_timeout = Duration::from_secs(var_or("QUERY_TIMEOUT", DEFAULT_QUERY_TIMEOUT));
+
+        let api_keys: Arc<HashSet<String>> =
+            Arc::new(list_var("API_KEYS", "").into_iter().collect());
+
+        let gated_routes = Arc::new(list_var("GATED_ROUTES", DEFAULT_GATED_ROUTES));
+
+        if api_keys.is_empty() && !gated_routes.is_empty() {
+            #[cfg(not(debug_assertions))]
+            panic!(
+                "API_KEYS must be set to gate {}, set GATED_ROUTES to an empty value to make them public",
+                gated_routes.join(",")
+            );
+
+            #[cfg(debug_assertions)]
+            tracing::warn!(
+                "API_KEYS isn't set, the gated routes {} are public",
+                gated_routes.join(",")
+            );
+        }
+
         let db_url = ensure_var("DATABASE_URL");
 
         let pool = sqlx::postgres::PgPoolOptions::new()
@@ -129,6 +212,89 @@ impl AppState {
             atlas_route,
             atlas_auth,
             pool,
+            similar_min_completion,
+            similarity_candidate_cap,
+            expensive_queries,
+            expensive_query_timeout,
+            default_sort,
+            query_timeout,
+            api_keys,
+            gated_routes,
+        }
+    }
+
+    /// Starts a transaction whose statements are cancelled by Postgres once they run
+    /// longer than the query timeout. Heavy queries should run in one through
+    /// [`AppState::timed`], dropping it rolls back and releases the connection.
+    pub async fn timed_transaction(
+        &self,
+    ) -> Result<sqlx::Transaction<'static, sqlx::Postgres>, Response> {
+        let internal_error = |e: sqlx::Error| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to start query: {e}"),
+            )
+                .into_response()
+        };
+
+        let mut transaction = self.pool.begin().await.map_err(internal_error)?;
+        sqlx::query(&format!(
+            "SET LOCAL statement_timeout = {}",
+            self.query_timeout.as_millis()
+        ))
+        .execute(&mut transaction)
+        .await
+        .map_err(internal_error)?;
+
+        Ok(transaction)
+    }
+
+    /// Waits for a heavy query for at most the query timeout, answering `504` when it
+    /// takes longer or Postgres cancels it.
+    pub async fn timed<T>(
+        &self,
+        query: impl Future<Output = Result<T, sqlx::Error>>,
+        context: &str,
+    ) -> Result<T, Response> {
+        let timed_out = || {
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("{context}: the query took too long"),
+            )
+                .into_response()
+        };
+
+        match tokio::time::timeout(self.query_timeout, query).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) if is_query_canceled(&e) => Err(timed_out()),
+            Ok(Err(e)) => {
+                Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{context}: {e}")).into_response())
+            }
+            Err(_) => Err(timed_out()),
+        }
+    }
+
+    /// Waits for a slot to run an expensive query in, so a burst of heavy requests
+    /// can't take up the whole pool. Cheap queries shouldn't go through this.
+    pub async fn expensive_query_permit(&self) -> Result<OwnedSemaphorePermit, Response> {
+        let retry_after = self.expensive_query_timeout.as_secs().max(1).to_string();
+        let busy = || {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(header::RETRY_AFTER, retry_after.clone())],
+                "Too many expensive queries in progress, try again later".to_string(),
+            )
+                .into_response()
+        };
+
+        match tokio::time::timeout(
+            self.expensive_query_timeout,
+            self.expensive_queries.clone().acquire_owned(),
+        )
+        .await
+        {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => Err(busy()),
         }
     }
 }
@@ -136,3 +302,102 @@ impl AppState {
 pub fn ensure_var(name: &str) -> String {
     std::env::var(name).unwrap_or_else(|_| panic!("{name} must be set"))
 }
+
+/// The current time in nanoseconds since the epoch, saturating past 2262 when it no
+/// longer fits.
+pub fn now_nanos() -> i64 {
+    chrono::Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX)
+}
+
+/// Whether Postgres cancelled the query, e.g. because of the statement timeout.
+fn is_query_canceled(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|error| error.code())
+        .is_some_and(|code| code == "57014")
+}
+
+/// Splits an optional comma separated variable, falling back to `default` when it
+/// isn't set. Empty items are skipped.
+pub fn list_var(name: &str, default: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses an optional variable, falling back to `default` when it isn't set.
+pub fn var_or<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{name} has an invalid value"))
+        })
+        .unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The app state with a one second query timeout, connected to `DATABASE_URL`.
+    async fn test_state() -> AppState {
+        AppState {
+            query_timeout: Duration::from_secs(1),
+            ..AppState::new().await
+        }
+    }
+
+    #[test]
+    fn test_is_query_canceled() {
+        // Only a cancelled statement counts, not e.g. a missing row
+        assert!(!is_query_canceled(&sqlx::Error::RowNotFound));
+        assert!(!is_query_canceled(&sqlx::Error::PoolTimedOut));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_statement_timeout() {
+        let state = test_state().await;
+
+        // Postgres cancels the statement on its own
+        let mut db_tx = state.timed_transaction().await.unwrap();
+        let error = sqlx::query("SELECT pg_sleep(5)")
+            .execute(&mut db_tx)
+            .await
+            .unwrap_err();
+        assert!(is_query_canceled(&error));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_timed_query_times_out() {
+        let state = test_state().await;
+
+        let mut db_tx = state.timed_transaction().await.unwrap();
+        let response = state
+            .timed(
+                sqlx::query("SELECT pg_sleep(5)").execute(&mut db_tx),
+                "Failed to sleep",
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        drop(db_tx);
+
+        // The connection went back to the pool and can be used again
+        let mut db_tx = state.timed_transaction().await.unwrap();
+        let one: i32 = state
+            .timed(
+                sqlx::query_scalar("SELECT 1").fetch_one(&mut db_tx),
+                "Failed to select",
+            )
+            .await
+            .unwrap();
+        assert_eq!(one, 1);
+    }
+}
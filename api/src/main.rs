@@ -1,3 +1,4 @@
+use api::auth::require_api_key;
 use api::AppState;
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
@@ -10,15 +11,19 @@ fn generate_key() -> String {
 
 #[tokio::main]
 async fn main() {
-    let state = AppState::new().await;
-
     tracing_subscriber::fmt::init();
 
+    let state = AppState::new().await;
+
     let trace = TraceLayer::new_for_http();
     let cors = CorsLayer::new().allow_methods(Any).allow_origin(Any);
     let middleware = ServiceBuilder::new().layer(trace).layer(cors);
 
     let app = api::routes::create_router()
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            require_api_key,
+        ))
         .with_state(state)
         .layer(middleware);
 
@@ -252,9 +252,7 @@ async fn get_completion(
         )
     })?;
 
-    let avg = list.iter().map(|c| c.completion).sum::<f64>() / list.len() as f64;
-
-    Ok(Json(Completion { avg, list }))
+    Ok(Json(Completion::new(list)))
 }
 
 pub fn create_router() -> Router<AppState> {
@@ -13,6 +13,7 @@ pub mod metrics;
 pub mod projects;
 pub mod proposals;
 pub mod requests;
+pub mod status;
 pub mod vendors;
 
 pub fn set_deserialize<'de, D>(deserializer: D) -> Result<Option<HashSet<String>>, D::Error>
@@ -39,6 +40,63 @@ pub struct Completion {
     list: Vec<CompletionPair>,
 }
 
+impl Completion {
+    /// Creates the completion summary, an empty list has an average of 0 so the
+    /// response stays valid JSON instead of containing `NaN`.
+    pub fn new(list: Vec<CompletionPair>) -> Self {
+        let avg = if list.is_empty() {
+            0.0
+        } else {
+            list.iter().map(|c| c.completion).sum::<f64>() / list.len() as f64
+        };
+
+        Self { avg, list }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct EnvelopeParams {
+    /// Wraps the list in a [`Page`], plain arrays are returned otherwise so older
+    /// clients keep working.
+    #[serde(default)]
+    pub envelope: bool,
+}
+
+/// A page of a list endpoint, returned when it's called with `envelope=true`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The number of items across all pages, if known.
+    pub total: Option<u64>,
+    pub from: u32,
+    pub limit: u32,
+    /// The `from` of the next page, `None` on the last one.
+    pub next: Option<u32>,
+}
+
+impl<T> Page<T> {
+    /// Wraps the `items` found starting at `from`. Without a `limit` the page holds
+    /// all of the remaining items.
+    pub fn new(
+        items: Vec<T>,
+        total: Option<u64>,
+        from: Option<u32>,
+        limit: Option<u32>,
+        has_more: bool,
+    ) -> Self {
+        let from = from.unwrap_or_default();
+        let next = has_more.then_some(from + items.len() as u32);
+
+        Self {
+            limit: limit.unwrap_or(items.len() as u32),
+            items,
+            total,
+            from,
+            next,
+        }
+    }
+}
+
 pub fn create_router() -> Router<AppState> {
     Router::new()
         .nest("/claims", claims::create_router())
@@ -49,5 +107,58 @@ pub fn create_router() -> Router<AppState> {
         .nest("/projects", projects::create_router())
         .nest("/proposals", proposals::create_router())
         .nest("/requests", requests::create_router())
+        .nest("/status", status::create_router())
         .nest("/vendors", vendors::create_router())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(id: &str, completion: f64) -> CompletionPair {
+        CompletionPair {
+            id: id.to_string(),
+            completion,
+        }
+    }
+
+    #[test]
+    fn test_completion() {
+        let completion = Completion::new(vec![pair("a.near", 0.5), pair("b.near", 1.0)]);
+        assert_eq!(completion.avg, 0.75);
+        assert_eq!(completion.list.len(), 2);
+
+        // No NaN for an empty list, so the response stays valid JSON
+        let completion = Completion::new(vec![]);
+        assert_eq!(completion.avg, 0.0);
+        assert_eq!(
+            serde_json::to_value(&completion).unwrap(),
+            serde_json::json!({ "avg": 0.0, "list": [] })
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let page = Page::new(vec![1, 2], Some(10), Some(4), Some(2), true);
+        assert_eq!(
+            page,
+            Page {
+                items: vec![1, 2],
+                total: Some(10),
+                from: 4,
+                limit: 2,
+                next: Some(6),
+            }
+        );
+
+        // The last page has no next one, without a limit it holds every item left
+        let page = Page::new(vec![1, 2, 3], None, None, None, false);
+        assert_eq!(page.from, 0);
+        assert_eq!(page.limit, 3);
+        assert_eq!(page.next, None);
+
+        // A short page still points past the items it has
+        let page = Page::new(vec![1], None, Some(4), Some(2), true);
+        assert_eq!(page.next, Some(5));
+    }
+}
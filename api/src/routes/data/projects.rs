@@ -1,20 +1,43 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 
 use axum::{
+    body::StreamBody,
     debug_handler,
     extract::{Path, Query, State},
-    routing::get,
+    http::{header, HeaderName},
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
 use crate::{
-    routes::data::{set_deserialize, Completion, CompletionPair},
-    AppState,
+    now_nanos,
+    routes::{
+        data::{set_deserialize, Completion, CompletionPair, EnvelopeParams, Page},
+        transactions::Transaction,
+    },
+    AppState, DEFAULT_SIMILARITY_CANDIDATE_CAP,
 };
 
+/// Parses a single `from-to` team size range, returning the offending token if it's
+/// malformed or `from` is larger than `to`.
+pub fn parse_size_range(range: &str) -> Result<(u32, u32), String> {
+    let invalid = || range.to_string();
+    let (from, to) = range.split_once('-').ok_or_else(invalid)?;
+    let from = from.trim().parse::<u32>().map_err(|_| invalid())?;
+    let to = to.trim().parse::<u32>().map_err(|_| invalid())?;
+    if from > to {
+        return Err(invalid());
+    }
+    Ok((from, to))
+}
+
 pub fn size_deserialize<'de, D>(deserializer: D) -> Result<Option<HashSet<(u32, u32)>>, D::Error>
 where
     D: serde::Deserializer<'de>,
@@ -25,34 +48,43 @@ where
     }
     Ok(Some(
         s.split(',')
-            .filter_map(|range| {
-                let parts: Vec<_> = range.split('-').collect();
-                if parts.is_empty() && parts.len() != 2 {
-                    return None;
-                }
-                let Some(from) = parts.first() else {
-                return None;
-            };
-                let Some(to) = parts.last() else {
-                return None;
-            };
-                let Ok(from) = from.parse::<u32>() else {
-                return None;
-            };
-                let Ok(to) = to.parse::<u32>() else {
-                return None;
-            };
-                if from > to {
-                    return None;
-                }
-                Some((from, to))
-            })
+            .filter_map(|range| parse_size_range(range).ok())
             .collect(),
     ))
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
-#[serde(rename_all = "lowercase")]
+/// Team size ranges along with the tokens that couldn't be parsed, so that strict
+/// requests can reject them while lenient ones just drop them.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SizeRanges {
+    pub ranges: HashSet<(u32, u32)>,
+    pub invalid: Vec<String>,
+}
+
+pub fn size_ranges_deserialize<'de, D>(deserializer: D) -> Result<Option<SizeRanges>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let mut sizes = SizeRanges::default();
+    for range in s.split(',') {
+        match parse_size_range(range) {
+            Ok(range) => {
+                sizes.ranges.insert(range);
+            }
+            Err(token) => sizes.invalid.push(token),
+        }
+    }
+    Ok(Some(sizes))
+}
+
+/// The project listing order. Besides the variant names, parsing ignores casing,
+/// `_` and `-` and accepts the `newest`, `oldest`, `az` and `za` aliases.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase", try_from = "String")]
 pub enum Sort {
     TimeAsc,
     #[default]
@@ -63,8 +95,34 @@ pub enum Sort {
     RecentDesc,
 }
 
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let normalized = value.to_lowercase().replace(['_', '-'], "");
+        match normalized.as_str() {
+            "timeasc" | "oldest" => Ok(Sort::TimeAsc),
+            "timedesc" | "newest" => Ok(Sort::TimeDesc),
+            "nameasc" | "az" => Ok(Sort::NameAsc),
+            "namedesc" | "za" => Ok(Sort::NameDesc),
+            "recentasc" => Ok(Sort::RecentAsc),
+            "recentdesc" => Ok(Sort::RecentDesc),
+            _ => Err(format!("unknown sort `{value}`")),
+        }
+    }
+}
+
+impl TryFrom<String> for Sort {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
 impl Sort {
-    pub fn get_order_by(&self) -> (&'static str, &'static str) {
+    /// The join needed by the sort, the expression to sort by and its direction.
+    pub fn get_order_by(&self) -> (&'static str, &'static str, &'static str) {
         match self {
             Sort::TimeAsc => (
                 r#"
@@ -81,7 +139,8 @@ impl Sort {
                     transactions.timestamp ASC
                 ) as txs ON projects.id = txs.account_id
                 "#,
-                "ORDER BY txs.timestamp ASC",
+                "txs.timestamp",
+                "ASC",
             ),
             Sort::TimeDesc => (
                 r#"
@@ -98,10 +157,11 @@ impl Sort {
                     transactions.timestamp DESC
                 ) as txs ON projects.id = txs.account_id
                 "#,
-                "ORDER BY txs.timestamp DESC",
+                "txs.timestamp",
+                "DESC",
             ),
-            Sort::NameAsc => ("", "ORDER BY NULLIF(projects.name, '') ASC NULLS LAST"),
-            Sort::NameDesc => ("", "ORDER BY NULLIF(projects.name, '') DESC NULLS LAST"),
+            Sort::NameAsc => ("", "NULLIF(projects.name, '')", "ASC NULLS LAST"),
+            Sort::NameDesc => ("", "NULLIF(projects.name, '')", "DESC NULLS LAST"),
             Sort::RecentAsc => (
                 r#"
                 LEFT JOIN (
@@ -133,7 +193,8 @@ impl Sort {
                     transactions.timestamp ASC
                 ) as txs ON projects.id = txs.account_id
                 "#,
-                "ORDER BY txs.timestamp ASC",
+                "txs.timestamp",
+                "ASC",
             ),
             Sort::RecentDesc => (
                 r#"
@@ -166,104 +227,202 @@ impl Sort {
                     transactions.timestamp DESC
                 ) as txs ON projects.id = txs.account_id
                 "#,
-                "ORDER BY txs.timestamp DESC",
+                "txs.timestamp",
+                "DESC",
             ),
         }
     }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VerticalMatch {
+    #[default]
+    Any,
+    All,
+}
+
+impl VerticalMatch {
+    pub fn get_operator(&self) -> &'static str {
+        match self {
+            VerticalMatch::Any => "?|",
+            VerticalMatch::All => "?&",
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct Params {
-    #[serde(default)]
-    pub sort: Sort,
+    /// Falls back to the server default (`DEFAULT_SORT`) when not provided.
+    pub sort: Option<Sort>,
     #[serde(default, deserialize_with = "set_deserialize")]
     pub vertical: Option<HashSet<String>>,
+    #[serde(default)]
+    pub vertical_match: VerticalMatch,
     #[serde(default, deserialize_with = "set_deserialize")]
     pub integration: Option<HashSet<String>>,
     #[serde(default, deserialize_with = "set_deserialize")]
     pub dev: Option<HashSet<String>>,
     #[serde(default, deserialize_with = "set_deserialize")]
     pub stage: Option<HashSet<String>>,
-    #[serde(default, deserialize_with = "size_deserialize")]
-    pub size: Option<HashSet<(u32, u32)>>,
+    #[serde(default, deserialize_with = "size_ranges_deserialize")]
+    pub size: Option<SizeRanges>,
     #[serde(default, deserialize_with = "set_deserialize")]
     pub distribution: Option<HashSet<String>>,
+    /// Only keep projects where each of these fields is filled in, see
+    /// [`presence_condition`] for the supported fields.
+    #[serde(default, deserialize_with = "set_deserialize")]
+    pub has: Option<HashSet<String>>,
+    /// Only keep the projects with these ids, at most [`MAX_IDS`] of them.
+    #[serde(default, deserialize_with = "set_deserialize")]
+    pub ids: Option<HashSet<String>>,
     pub from: Option<u32>,
     pub limit: Option<u32>,
     #[serde(rename = "q")]
     pub search: Option<String>,
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Reject malformed filters with a `400` instead of ignoring them.
+    #[serde(default)]
+    pub strict: bool,
 }
 
-#[debug_handler(state = AppState)]
-pub async fn all_projects(
-    Query(params): Query<Params>,
-    State(AppState { pool, .. }): State<AppState>,
-) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    let mut builder = sqlx::QueryBuilder::new(
-        r#"
-        SELECT
-          projects.id
-        FROM
-          projects
-        "#,
-    );
+/// The maximum number of ids the project listing can be narrowed down to.
+pub const MAX_IDS: usize = 200;
 
-    let (join, order_by) = params.sort.get_order_by();
+/// The condition checking that a project's field is filled in, `None` if the field
+/// can't be used with the `has` filter.
+pub fn presence_condition(field: &str) -> Option<&'static str> {
+    Some(match field {
+        "tagline" => "NULLIF(TRIM(projects.tagline), '') IS NOT NULL",
+        "description" => "NULLIF(TRIM(projects.description), '') IS NOT NULL",
+        "website" => "NULLIF(TRIM(projects.website), '') IS NOT NULL",
+        "deck" => "NULLIF(TRIM(projects.deck), '') IS NOT NULL",
+        "white_paper" => "NULLIF(TRIM(projects.white_paper), '') IS NOT NULL",
+        "roadmap" => "NULLIF(TRIM(projects.roadmap), '') IS NOT NULL",
+        "demo" => "NULLIF(TRIM(projects.demo), '') IS NOT NULL",
+        "integration" => "NULLIF(TRIM(projects.integration), '') IS NOT NULL",
+        "founders" => "COALESCE(array_length(projects.founders, 1), 0) > 0",
+        "team" => "projects.team NOT IN ('null', '{}', '[]')",
+        "image" => "projects.image NOT IN ('null', '{}', '[]')",
+        "linktree" => "projects.linktree NOT IN ('null', '{}', '[]')",
+        _ => return None,
+    })
+}
 
-    builder.push(join);
+impl Params {
+    /// Makes sure the `has` fields are supported, that there aren't too many `ids`
+    /// and that a strict request doesn't contain any malformed size ranges.
+    pub fn validate(&self) -> Result<(), (StatusCode, String)> {
+        if self.ids.as_ref().map_or(0, HashSet::len) > MAX_IDS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Can't filter by more than {MAX_IDS} ids at once"),
+            ));
+        }
+
+        if let Some(field) = self
+            .has
+            .iter()
+            .flatten()
+            .find(|field| presence_condition(field).is_none())
+        {
+            return Err((StatusCode::BAD_REQUEST, format!("INVALID_FIELD: {field}")));
+        }
+
+        if !self.strict {
+            return Ok(());
+        }
+        match self.size.as_ref().and_then(|size| size.invalid.first()) {
+            Some(token) => Err((
+                StatusCode::BAD_REQUEST,
+                format!("INVALID_SIZE_RANGE: {token}"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Joins the latest `remove_project` and the latest `add_project`/`edit_project`
+/// timestamps of each project, to be filtered with [`NOT_REMOVED`].
+pub const REMOVALS_JOIN: &str = r#"
+    LEFT JOIN (
+      SELECT
+        transactions.args ->> 'account_id' AS account_id,
+        MAX(transactions.timestamp) FILTER (
+          WHERE transactions.method_name = 'remove_project'
+        ) AS removed_at,
+        MAX(transactions.timestamp) FILTER (
+          WHERE transactions.method_name IN ('add_project', 'edit_project')
+        ) AS upserted_at
+      FROM
+        transactions
+      WHERE
+        transactions.method_name IN ('add_project', 'edit_project', 'remove_project')
+      GROUP BY
+        transactions.args ->> 'account_id'
+    ) AS removals ON projects.id = removals.account_id
+    "#;
+
+/// Keeps projects that were never removed or were added again after the removal.
+pub const NOT_REMOVED: &str =
+    " (removals.removed_at IS NULL OR removals.removed_at < removals.upserted_at) ";
 
+/// Starts the next condition of the `WHERE` clause built by [`apply_filters`].
+fn push_condition(builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, has_where: &mut bool) {
+    if *has_where {
+        builder.push(" AND ");
+    } else {
+        builder.push(" WHERE ");
+        *has_where = true;
+    }
+}
+
+/// Adds the removals join and the `WHERE` clause narrowing `projects` down to the
+/// ones matching the `params`. Every endpoint filtering projects goes through here
+/// so they all agree on what a filter means, sorting and paging are left to the
+/// caller.
+fn apply_filters(builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, params: &Params) {
     let mut has_where = false;
 
-    if let Some(verticals) = params.vertical {
-        let verticals = verticals.into_iter().collect::<Vec<_>>();
-        builder.push("WHERE projects.vertical ?| ");
-        builder.push_bind(verticals);
-        has_where = true;
+    if !params.include_deleted {
+        builder.push(REMOVALS_JOIN);
+        push_condition(builder, &mut has_where);
+        builder.push(NOT_REMOVED);
     }
 
-    if let Some(integration) = params.integration {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push("WHERE ");
-            has_where = true;
-        }
+    if let Some(verticals) = &params.vertical {
+        push_condition(builder, &mut has_where);
+        builder.push(format!(
+            "projects.vertical {} ",
+            params.vertical_match.get_operator()
+        ));
+        builder.push_bind(verticals.iter().cloned().collect::<Vec<_>>());
+    }
+
+    if let Some(integration) = &params.integration {
+        push_condition(builder, &mut has_where);
         builder.push("projects.integration = ANY (");
-        builder.push_bind(integration.into_iter().collect::<Vec<_>>());
+        builder.push_bind(integration.iter().cloned().collect::<Vec<_>>());
         builder.push(") ");
     }
 
-    if let Some(stage) = params.stage {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push("WHERE ");
-            has_where = true;
-        }
+    if let Some(stage) = &params.stage {
+        push_condition(builder, &mut has_where);
         builder.push("projects.stage = ANY (");
-        builder.push_bind(stage.into_iter().collect::<Vec<_>>());
+        builder.push_bind(stage.iter().cloned().collect::<Vec<_>>());
         builder.push(") ");
     }
 
-    if let Some(dev) = params.dev {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push("WHERE ");
-            has_where = true;
-        }
+    if let Some(dev) = &params.dev {
+        push_condition(builder, &mut has_where);
         builder.push("projects.dev = ANY (");
-        builder.push_bind(dev.into_iter().collect::<Vec<_>>());
+        builder.push_bind(dev.iter().cloned().collect::<Vec<_>>());
         builder.push(") ");
     }
 
-    if let Some(sizes) = params.size {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push("WHERE ");
-            has_where = true;
-        }
+    if let Some(sizes) = params.size.as_ref().filter(|size| !size.ranges.is_empty()) {
+        push_condition(builder, &mut has_where);
         builder.push(" ( ");
         let statement = r#"
         (
@@ -274,49 +433,124 @@ pub async fn all_projects(
         ) + array_length(projects.founders, 1) BETWEEN
         "#;
 
-        for (i, (from, to)) in sizes.into_iter().enumerate() {
+        for (i, (from, to)) in sizes.ranges.iter().enumerate() {
             if i > 0 {
                 builder.push(" OR ");
             }
             builder.push(statement);
-            builder.push_bind(from as i32);
+            builder.push_bind(*from as i32);
             builder.push(" AND ");
-            builder.push_bind(to as i32);
+            builder.push_bind(*to as i32);
         }
         builder.push(" ) ");
     }
 
-    if let Some(distribution) = params.distribution {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push("WHERE ");
-            has_where = true;
-        }
+    if let Some(distribution) = &params.distribution {
+        push_condition(builder, &mut has_where);
         builder.push("projects.distribution = ANY (");
-        builder.push_bind(distribution.into_iter().collect::<Vec<_>>());
+        builder.push_bind(distribution.iter().cloned().collect::<Vec<_>>());
         builder.push(") ");
     }
 
-    if let Some(search) = params.search {
-        if has_where {
-            builder.push(" AND ");
-        } else {
-            builder.push(" WHERE ");
-        }
+    if let Some(ids) = &params.ids {
+        push_condition(builder, &mut has_where);
+        builder.push("projects.id = ANY (");
+        builder.push_bind(ids.iter().cloned().collect::<Vec<_>>());
+        builder.push(") ");
+    }
+
+    for condition in params
+        .has
+        .iter()
+        .flatten()
+        .filter_map(|field| presence_condition(field))
+    {
+        push_condition(builder, &mut has_where);
+        builder.push(format!(" {condition} "));
+    }
+
+    if let Some(search) = &params.search {
+        push_condition(builder, &mut has_where);
+        // Text fields ignore accents (e.g. "cafe" matches "Café"), ids are matched as is
         let search = format!("%{search}%");
-        builder.push(" (projects.name ILIKE ");
+        builder.push(" (unaccent(projects.name) ILIKE unaccent(");
         builder.push_bind(search.clone());
-        builder.push(" OR projects.id ILIKE ");
+        builder.push(") OR projects.id ILIKE ");
         builder.push_bind(search.clone());
-        builder.push(" OR projects.tagline ILIKE ");
+        builder.push(" OR unaccent(projects.tagline) ILIKE unaccent(");
         builder.push_bind(search.clone());
-        builder.push(" OR projects.description ILIKE ");
+        builder.push(") OR unaccent(projects.description) ILIKE unaccent(");
         builder.push_bind(search);
-        builder.push(") ");
+        builder.push(")) ");
     }
+}
+
+/// Counts the projects matching the `params`, ignoring the sort and the page. This
+/// is the same number as the listing's `total`.
+async fn filter_count(
+    executor: impl sqlx::PgExecutor<'_>,
+    params: &Params,
+) -> Result<u64, sqlx::Error> {
+    let mut builder = sqlx::QueryBuilder::new(
+        r#"
+        SELECT
+          COUNT(DISTINCT projects.id) AS total
+        FROM
+          projects
+        "#,
+    );
+    apply_filters(&mut builder, params);
+
+    let row = builder.build().fetch_one(executor).await?;
+    Ok(row.get::<i64, _>("total") as u64)
+}
+
+/// Builds the query selecting the ids of all the projects matching the `params`,
+/// along with the `total` number of matches ignoring the page when `with_total` is
+/// set. Counting needs the whole result before the first row, so streams leave it
+/// out. Joins can match a project more than once, so `DISTINCT ON` keeps each
+/// project's best ranked row before the outer query applies the requested order.
+fn build_projects_query(
+    params: Params,
+    default_sort: Sort,
+    with_total: bool,
+) -> sqlx::QueryBuilder<'static, sqlx::Postgres> {
+    let (join, sort_key, direction) = params.sort.unwrap_or(default_sort).get_order_by();
+    let total = if with_total {
+        ",\n          COUNT(*) OVER () AS total"
+    } else {
+        ""
+    };
+
+    let mut builder = sqlx::QueryBuilder::new(format!(
+        r#"
+        SELECT
+          ranked.id{total}
+        FROM
+          (
+            SELECT
+              DISTINCT ON (projects.id) projects.id,
+              {sort_key} AS sort_key
+            FROM
+              projects
+        "#
+    ));
 
-    builder.push(format!(" {order_by}"));
+    builder.push(join);
+
+    apply_filters(&mut builder, &params);
+
+    builder.push(format!(
+        r#"
+            ORDER BY
+              projects.id,
+              {sort_key} {direction}
+          ) AS ranked
+        ORDER BY
+          ranked.sort_key {direction},
+          ranked.id ASC
+        "#
+    ));
 
     if let Some(limit) = params.limit {
         builder.push(" LIMIT ");
@@ -328,6 +562,23 @@ pub async fn all_projects(
         builder.push_bind(from as i32);
     }
 
+    builder
+}
+
+#[debug_handler(state = AppState)]
+pub async fn all_projects(
+    Query(params): Query<Params>,
+    Query(EnvelopeParams { envelope }): Query<EnvelopeParams>,
+    Query(CreditsParams { with_credits }): Query<CreditsParams>,
+    State(AppState {
+        pool, default_sort, ..
+    }): State<AppState>,
+) -> Result<Response, (StatusCode, String)> {
+    params.validate()?;
+
+    let (from, limit) = (params.from, params.limit);
+    let mut builder = build_projects_query(params, default_sort, true);
+
     let result = builder.build().fetch_all(&pool).await.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -335,34 +586,220 @@ pub async fn all_projects(
         )
     })?;
 
-    Ok(Json({
-        let mut seen = HashSet::<String>::new();
-        result
-            .into_iter()
-            .filter_map(|r| {
-                let id: String = r.get("id");
-                (!seen.contains(&id)).then_some({
-                    seen.insert(id.clone());
-                    id
-                })
-            })
-            .collect()
-    }))
+    let total = result
+        .first()
+        .map(|r| r.get::<i64, _>("total") as u64)
+        // An empty first page means there are no matches at all
+        .or_else(|| (from.unwrap_or_default() == 0).then_some(0));
+    let ids: Vec<String> = result.into_iter().map(|r| r.get("id")).collect();
+    let has_more =
+        total.is_some_and(|total| from.unwrap_or_default() as u64 + (ids.len() as u64) < total);
+
+    if !with_credits {
+        let page = Page::new(ids, total, from, limit, has_more);
+        return Ok(listing_response(page, envelope));
+    }
+
+    let registered: HashSet<String> = sqlx::query_scalar!(
+        r#"
+        SELECT
+          DISTINCT credits_registrations.account_id
+        FROM
+          credits_registrations
+        WHERE
+          credits_registrations.account_id = ANY ($1)
+        "#,
+        &ids
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get credits registrations: {e}"),
+        )
+    })?
+    .into_iter()
+    .collect();
+
+    let projects: Vec<ListedProject> = ids
+        .into_iter()
+        .map(|id| ListedProject {
+            credits_registered: registered.contains(&id),
+            id,
+        })
+        .collect();
+
+    let page = Page::new(projects, total, from, limit, has_more);
+    Ok(listing_response(page, envelope))
+}
+
+/// Returns the page as is when the envelope was asked for, or just its items.
+fn listing_response<T: Serialize>(page: Page<T>, envelope: bool) -> Response {
+    if envelope {
+        Json(page).into_response()
+    } else {
+        Json(page.items).into_response()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct CreditsParams {
+    /// Lists projects as [`ListedProject`]s instead of plain ids.
+    #[serde(default)]
+    pub with_credits: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListedProject {
+    pub id: String,
+    /// Whether the project is on the credits token's allowlist, as recorded from the
+    /// token's events by the indexer.
+    pub credits_registered: bool,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ProjectCount {
+    pub total: u64,
+}
+
+/// The number of projects [`all_projects`] would list for the same filters.
 #[debug_handler(state = AppState)]
-pub async fn get_similar_projects(
-    Path(account_id): Path<String>,
+pub async fn get_projects_count(
+    Query(params): Query<Params>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<ProjectCount>, (StatusCode, String)> {
+    params.validate()?;
+
+    let total = filter_count(&pool, &params).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to count projects: {e}"),
+        )
+    })?;
+
+    Ok(Json(ProjectCount { total }))
+}
+
+/// The board column of projects without a stage.
+pub const UNKNOWN_STAGE: &str = "unknown";
+
+/// The ids of the projects matching the filters grouped by stage, with projects
+/// without one under [`UNKNOWN_STAGE`]. Sorting and paging don't apply, ids are in
+/// alphabetical order within each stage.
+#[debug_handler(state = AppState)]
+pub async fn get_board(
+    Query(params): Query<Params>,
     State(AppState { pool, .. }): State<AppState>,
-) -> Result<Json<Vec<String>>, (StatusCode, String)> {
-    let project = sqlx::query!(
+) -> Result<Json<BTreeMap<String, Vec<String>>>, (StatusCode, String)> {
+    params.validate()?;
+
+    let mut builder = sqlx::QueryBuilder::new(
+        r#"
+        SELECT
+          DISTINCT projects.id,
+          projects.stage
+        FROM
+          projects
+        "#,
+    );
+    apply_filters(&mut builder, &params);
+    builder.push(" ORDER BY projects.id ASC");
+
+    let rows = builder.build().fetch_all(&pool).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get projects: {e}"),
+        )
+    })?;
+
+    let mut board: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for row in rows {
+        let stage: String = row.get("stage");
+        let stage = match stage.trim() {
+            "" => UNKNOWN_STAGE.to_string(),
+            stage => stage.to_string(),
+        };
+        board.entry(stage).or_default().push(row.get("id"));
+    }
+
+    Ok(Json(board))
+}
+
+/// Streams the same listing as [`all_projects`] as newline delimited JSON objects
+/// so the client can start rendering before the whole result set is fetched.
+#[debug_handler(state = AppState)]
+pub async fn stream_projects(
+    Query(params): Query<Params>,
+    State(AppState {
+        pool, default_sort, ..
+    }): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    params.validate()?;
+
+    let (sender, receiver) = mpsc::channel::<Result<String, sqlx::Error>>(64);
+
+    tokio::spawn(async move {
+        let mut builder = build_projects_query(params, default_sort, false);
+        let mut rows = builder.build().fetch(&pool);
+
+        while let Some(row) = rows.next().await {
+            let line = row.map(|row| {
+                let id: String = row.get("id");
+                format!("{}\n", serde_json::json!({ "id": id }))
+            });
+
+            if sender.send(line).await.is_err() {
+                // The client has disconnected so there is no point in fetching further
+                break;
+            }
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(ReceiverStream::new(receiver)),
+    ))
+}
+
+/// Ids of the projects most similar to `account_id`, best matches first. Projects
+/// with the same score are ordered by id so pages never overlap. Team size (team
+/// members and founders) adds less than a full matching attribute, so it mostly
+/// orders projects that match on the same attributes.
+///
+/// Only projects sharing a vertical or a product type with the target are scored,
+/// found through the GIN indexes on both columns, and at most `candidate_cap` of
+/// them (the most complete ones) so the cost stays bounded as the directory grows.
+#[allow(clippy::too_many_arguments)]
+async fn similar_project_ids(
+    executor: impl sqlx::PgExecutor<'_>,
+    account_id: &str,
+    min_completion: f64,
+    include_deleted: bool,
+    size_tolerance: Option<u32>,
+    candidate_cap: i64,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let sql = format!(
         r#"
         SELECT
           projects.id
         FROM
           (
             SELECT
-              *
+              projects.*,
+              (
+                CASE jsonb_typeof(projects.team)
+                  WHEN 'object' THEN (
+                    SELECT
+                      COUNT(*)
+                    FROM
+                      jsonb_object_keys(projects.team)
+                  )
+                  ELSE 0
+                END
+              ) + COALESCE(array_length(projects.founders, 1), 0) AS team_size
             FROM
               projects
             WHERE
@@ -370,32 +807,50 @@ pub async fn get_similar_projects(
           ) AS target,
           (
             SELECT
-              *
+              projects.*,
+              (
+                CASE jsonb_typeof(projects.team)
+                  WHEN 'object' THEN (
+                    SELECT
+                      COUNT(*)
+                    FROM
+                      jsonb_object_keys(projects.team)
+                  )
+                  ELSE 0
+                END
+              ) + COALESCE(array_length(projects.founders, 1), 0) AS team_size
             FROM
-              projects
+              (
+                SELECT
+                  candidates.*
+                FROM
+                  projects AS candidates,
+                  projects AS target
+                WHERE
+                  target.id = $1
+                  AND candidates.id != $1
+                  AND candidates.completion >= $2
+                  AND (
+                    candidates.vertical ?| ARRAY(
+                      SELECT
+                        jsonb_object_keys(target.vertical)
+                    )
+                    OR candidates.product_type && target.product_type
+                  )
+                ORDER BY
+                  candidates.completion DESC,
+                  candidates.id ASC
+                LIMIT
+                  $7
+              ) AS projects
+              {REMOVALS_JOIN}
             WHERE
-              id != $1
+              $3
+              OR {NOT_REMOVED}
           ) AS projects
         WHERE
-          (
-            SELECT
-              COUNT(*)
-            FROM
-              jsonb_object_keys(projects.vertical) AS v
-            WHERE
-              target.vertical ? v
-          ) > 0
-          OR (
-            SELECT
-              COUNT(*)
-            FROM
-              unnest(projects.product_type) AS v
-            WHERE
-              v = ANY (target.product_type)
-          ) > 0
-          OR projects.stage ILIKE target.stage
-          OR projects.dev ILIKE target.dev
-          OR projects.distribution ILIKE target.distribution
+          $6 :: int IS NULL
+          OR ABS(projects.team_size - target.team_size) <= $6
         ORDER BY
           (
             SELECT
@@ -417,20 +872,916 @@ pub async fn get_similar_projects(
             projects.dev ILIKE target.dev
           ) :: int + (
             projects.distribution ILIKE target.distribution
-          ) :: int DESC
-        "#,
-        account_id
+          ) :: int + 1.0 / (
+            1 + ABS(projects.team_size - target.team_size)
+          ) DESC,
+          projects.id ASC
+        LIMIT
+          $4
+        OFFSET
+          $5
+        "#
+    );
+
+    let projects = sqlx::query(&sql)
+        .bind(account_id)
+        .bind(min_completion)
+        .bind(include_deleted)
+        .bind(limit)
+        .bind(offset)
+        .bind(size_tolerance.map(|tolerance| tolerance as i32))
+        .bind(candidate_cap)
+        .fetch_all(executor)
+        .await?;
+
+    Ok(projects.into_iter().map(|row| row.get("id")).collect())
+}
+
+/// How many neighbors of each project the `project_similarity` view keeps.
+pub const SIMILARITY_CACHE_SIZE: usize = 100;
+
+/// Like [`similar_project_ids`], but read from the `project_similarity` view the
+/// aggregator refreshes after syncing projects. Falls back to the live query when
+/// the project isn't in the view yet, when filtering by team size, when the page
+/// goes past the neighbors the view keeps, or when the candidate cap isn't the
+/// [`DEFAULT_SIMILARITY_CANDIDATE_CAP`] the view was built with.
+#[allow(clippy::too_many_arguments)]
+async fn cached_similar_project_ids(
+    connection: &mut sqlx::PgConnection,
+    account_id: &str,
+    min_completion: f64,
+    include_deleted: bool,
+    size_tolerance: Option<u32>,
+    candidate_cap: i64,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> Result<Vec<String>, sqlx::Error> {
+    if size_tolerance.is_none() && candidate_cap == DEFAULT_SIMILARITY_CANDIDATE_CAP {
+        let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new(
+            r#"
+            SELECT
+              projects.id,
+              COALESCE(projects.completion >= "#,
+        );
+        builder.push_bind(min_completion);
+        builder.push(" AND (");
+        builder.push_bind(include_deleted);
+        builder.push(format!(
+            r#"
+              OR {NOT_REMOVED}), FALSE) AS visible
+            FROM
+              project_similarity
+              JOIN projects ON projects.id = project_similarity.similar_id
+            "#
+        ));
+        builder.push(REMOVALS_JOIN);
+        builder.push("WHERE project_similarity.project_id = ");
+        builder.push_bind(account_id);
+        builder.push(" ORDER BY project_similarity.rank ASC");
+
+        let rows = builder.build().fetch_all(&mut *connection).await?;
+        // Projects with fewer neighbors than the view keeps have all of them cached
+        let complete = rows.len() < SIMILARITY_CACHE_SIZE;
+        let cached = !rows.is_empty();
+        let ids: Vec<String> = rows
+            .into_iter()
+            .filter(|row| row.get::<bool, _>("visible"))
+            .map(|row| row.get("id"))
+            .collect();
+
+        let offset = offset.unwrap_or_default() as usize;
+        let in_cache = limit.is_some_and(|limit| offset + limit as usize <= ids.len());
+        if cached && (complete || in_cache) {
+            return Ok(ids
+                .into_iter()
+                .skip(offset)
+                .take(limit.map_or(usize::MAX, |limit| limit as usize))
+                .collect());
+        }
+    }
+
+    similar_project_ids(
+        connection,
+        account_id,
+        min_completion,
+        include_deleted,
+        size_tolerance,
+        candidate_cap,
+        limit,
+        offset,
     )
-    .fetch_all(&pool)
     .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to get project: {e}"),
-        )
-    })?;
+}
 
-    Ok(Json(project.into_iter().map(|p| p.id).collect()))
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct SimilarParams {
+    /// Only return projects with at least this completion. It can only raise the
+    /// floor configured on the server through `SIMILAR_MIN_COMPLETION`, never lower it.
+    pub min_completion: Option<f64>,
+    #[serde(default)]
+    pub include_deleted: bool,
+    /// Only return projects whose team size differs by at most this many people.
+    pub size_tolerance: Option<u32>,
+    pub from: Option<u32>,
+    pub limit: Option<u32>,
+    /// Returns each project with its score and the reasons it matched instead of
+    /// just its id.
+    #[serde(default)]
+    pub explain: bool,
+}
+
+/// What a similar project has in common with the project it was matched to.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SimilarityReasons {
+    pub shared_verticals: Vec<String>,
+    pub shared_product_types: Vec<String>,
+    pub same_stage: bool,
+    pub same_dev: bool,
+    pub same_distribution: bool,
+    /// How many more or fewer people (team members and founders) the project has.
+    pub team_size_difference: i32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SimilarProject {
+    pub id: String,
+    /// The score the similar projects are ordered by, see [`similar_project_ids`].
+    pub score: f64,
+    pub reasons: SimilarityReasons,
+}
+
+/// Scores the `ids` against `account_id` like [`similar_project_ids`] does, along
+/// with the reasons behind each score. A missing stage, dev or distribution counts
+/// as not matching. Projects are returned in the order of the `ids`.
+async fn explain_similarity(
+    executor: impl sqlx::PgExecutor<'_>,
+    account_id: &str,
+    ids: &[String],
+) -> Result<Vec<SimilarProject>, sqlx::Error> {
+    let mut rows: HashMap<String, SimilarProject> = sqlx::query!(
+        r#"
+        WITH sized AS (
+          SELECT
+            projects.*,
+            (
+              CASE jsonb_typeof(projects.team)
+                WHEN 'object' THEN (
+                  SELECT
+                    COUNT(*)
+                  FROM
+                    jsonb_object_keys(projects.team)
+                )
+                ELSE 0
+              END
+            ) + COALESCE(array_length(projects.founders, 1), 0) AS team_size
+          FROM
+            projects
+          WHERE
+            projects.id = $1
+            OR projects.id = ANY ($2)
+        )
+        SELECT
+          candidate.id,
+          ARRAY(
+            SELECT
+              v
+            FROM
+              jsonb_object_keys(candidate.vertical) AS v
+            WHERE
+              target.vertical ? v
+            ORDER BY
+              v
+          ) AS "shared_verticals!",
+          ARRAY(
+            SELECT
+              DISTINCT v
+            FROM
+              unnest(candidate.product_type) AS v
+            WHERE
+              v = ANY (target.product_type)
+            ORDER BY
+              v
+          ) AS "shared_product_types!",
+          COALESCE(candidate.stage ILIKE target.stage, FALSE) AS "same_stage!",
+          COALESCE(candidate.dev ILIKE target.dev, FALSE) AS "same_dev!",
+          COALESCE(
+            candidate.distribution ILIKE target.distribution,
+            FALSE
+          ) AS "same_distribution!",
+          (candidate.team_size - target.team_size) :: int AS "team_size_difference!",
+          (
+            (
+              SELECT
+                COUNT(*)
+              FROM
+                unnest(candidate.product_type) AS v
+              WHERE
+                v = ANY (target.product_type)
+            ) + (
+              SELECT
+                COUNT(*)
+              FROM
+                jsonb_object_keys(candidate.vertical) AS v
+              WHERE
+                target.vertical ? v
+            ) + COALESCE((candidate.stage ILIKE target.stage) :: int, 0) + COALESCE(
+              (candidate.dev ILIKE target.dev) :: int,
+              0
+            ) + COALESCE(
+              (
+                candidate.distribution ILIKE target.distribution
+              ) :: int,
+              0
+            ) + 1.0 / (
+              1 + ABS(candidate.team_size - target.team_size)
+            )
+          ) :: float8 AS "score!"
+        FROM
+          sized AS target,
+          sized AS candidate
+        WHERE
+          target.id = $1
+          AND candidate.id = ANY ($2)
+        "#,
+        account_id,
+        ids
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let project = SimilarProject {
+            id: row.id.clone(),
+            score: row.score,
+            reasons: SimilarityReasons {
+                shared_verticals: row.shared_verticals,
+                shared_product_types: row.shared_product_types,
+                same_stage: row.same_stage,
+                same_dev: row.same_dev,
+                same_distribution: row.same_distribution,
+                team_size_difference: row.team_size_difference,
+            },
+        };
+        (row.id, project)
+    })
+    .collect();
+
+    Ok(ids.iter().filter_map(|id| rows.remove(id)).collect())
+}
+
+/// Header holding the `from` value of the next page when more results remain.
+pub const NEXT_FROM_HEADER: &str = "x-next-from";
+
+#[debug_handler(state = AppState)]
+pub async fn get_similar_projects(
+    Path(account_id): Path<String>,
+    Query(params): Query<SimilarParams>,
+    Query(EnvelopeParams { envelope }): Query<EnvelopeParams>,
+    State(state): State<AppState>,
+) -> Result<Response, Response> {
+    let _permit = state.expensive_query_permit().await?;
+
+    let min_completion = params
+        .min_completion
+        .map_or(state.similar_min_completion, |min| {
+            min.max(state.similar_min_completion)
+        });
+
+    let mut db_tx = state.timed_transaction().await?;
+    let mut ids = state
+        .timed(
+            cached_similar_project_ids(
+                &mut db_tx,
+                &account_id,
+                min_completion,
+                params.include_deleted,
+                params.size_tolerance,
+                state.similarity_candidate_cap,
+                // Fetch one extra row to know whether there is a next page
+                params.limit.map(|limit| limit as i64 + 1),
+                params.from.map(|from| from as i64),
+            ),
+            "Failed to get similar projects",
+        )
+        .await?;
+
+    let mut next = None;
+
+    if let Some(limit) = params.limit {
+        if ids.len() > limit as usize {
+            ids.truncate(limit as usize);
+            next = Some(params.from.unwrap_or(0) + limit);
+        }
+    }
+
+    if !params.explain {
+        return Ok(similar_response(ids, envelope, &params, next));
+    }
+
+    let projects = state
+        .timed(
+            explain_similarity(&mut db_tx, &account_id, &ids),
+            "Failed to explain similar projects",
+        )
+        .await?;
+
+    Ok(similar_response(projects, envelope, &params, next))
+}
+
+/// Wraps a page of similar projects in a [`Page`] or returns them as is, with the
+/// next page in the [`NEXT_FROM_HEADER`].
+fn similar_response<T: Serialize>(
+    items: Vec<T>,
+    envelope: bool,
+    params: &SimilarParams,
+    next: Option<u32>,
+) -> Response {
+    if envelope {
+        let page = Page::new(items, None, params.from, params.limit, next.is_some());
+        return Json(page).into_response();
+    }
+
+    let mut response = Json(items).into_response();
+    if let Some(next) = next {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(NEXT_FROM_HEADER), next.into());
+    }
+
+    response
+}
+
+/// The maximum number of ids that can be resolved in a single [`get_names`] call.
+pub const MAX_NAMES: usize = 200;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct NamesBody {
+    pub ids: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct ProjectName {
+    pub name: String,
+    pub tagline: String,
+}
+
+#[debug_handler(state = AppState)]
+pub async fn get_names(
+    State(AppState { pool, .. }): State<AppState>,
+    Json(NamesBody { ids }): Json<NamesBody>,
+) -> Result<Json<HashMap<String, ProjectName>>, (StatusCode, String)> {
+    if ids.len() > MAX_NAMES {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Can't resolve more than {MAX_NAMES} ids at once"),
+        ));
+    }
+
+    let names = sqlx::query!(
+        r#"
+        SELECT
+          projects.id,
+          projects.name,
+          projects.tagline
+        FROM
+          projects
+        WHERE
+          projects.id = ANY ($1)
+        "#,
+        &ids
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get project names: {e}"),
+        )
+    })?;
+
+    Ok(Json(
+        names
+            .into_iter()
+            .map(|p| {
+                (
+                    p.id,
+                    ProjectName {
+                        name: p.name,
+                        tagline: p.tagline,
+                    },
+                )
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct CompletionSnapshot {
+    pub timestamp: i64,
+    pub completion: f64,
+}
+
+#[debug_handler(state = AppState)]
+pub async fn get_completion_history(
+    Path(account_id): Path<String>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<Vec<CompletionSnapshot>>, (StatusCode, String)> {
+    sqlx::query_as!(
+        CompletionSnapshot,
+        r#"
+        SELECT
+          completion_snapshots.timestamp,
+          completion_snapshots.completion
+        FROM
+          completion_snapshots
+        WHERE
+          completion_snapshots.project_id = $1
+        ORDER BY
+          completion_snapshots.timestamp ASC,
+          completion_snapshots.id ASC
+        "#,
+        account_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get project completion history: {e}"),
+        )
+    })
+    .map(Json)
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DiffParams {
+    /// Hash of the older `add_project`/`edit_project` transaction.
+    pub from: String,
+    /// Hash of the newer `add_project`/`edit_project` transaction.
+    pub to: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ChangedField {
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+/// The fields that differ between two submissions of a project. Nested fields are
+/// keyed by their dotted path, e.g. `project.name`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ProjectDiff {
+    pub from: String,
+    pub to: String,
+    pub added: BTreeMap<String, serde_json::Value>,
+    pub removed: BTreeMap<String, serde_json::Value>,
+    pub changed: BTreeMap<String, ChangedField>,
+}
+
+/// Flattens the nested objects of `value` into `fields`, keyed by their dotted path.
+/// Arrays are compared as a whole so they're kept as a single field.
+fn flatten_fields(
+    prefix: Option<&str>,
+    value: serde_json::Value,
+    fields: &mut BTreeMap<String, serde_json::Value>,
+) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (key, value) in object {
+                let path = match prefix {
+                    Some(prefix) => format!("{prefix}.{key}"),
+                    None => key,
+                };
+                flatten_fields(Some(&path), value, fields);
+            }
+        }
+        value => {
+            fields.insert(prefix.unwrap_or_default().to_string(), value);
+        }
+    }
+}
+
+impl ProjectDiff {
+    pub fn new(
+        from: String,
+        to: String,
+        before: serde_json::Value,
+        after: serde_json::Value,
+    ) -> Self {
+        let mut before_fields = BTreeMap::new();
+        flatten_fields(None, before, &mut before_fields);
+        let mut after_fields = BTreeMap::new();
+        flatten_fields(None, after, &mut after_fields);
+
+        let mut diff = Self {
+            from,
+            to,
+            ..Default::default()
+        };
+        for (path, after) in after_fields {
+            match before_fields.remove(&path) {
+                None => {
+                    diff.added.insert(path, after);
+                }
+                Some(before) if before != after => {
+                    diff.changed.insert(path, ChangedField { before, after });
+                }
+                Some(_) => {}
+            }
+        }
+        diff.removed = before_fields;
+
+        diff
+    }
+}
+
+/// Compares the arguments of two `add_project`/`edit_project` transactions of the
+/// project, which hold the fields submitted with each edit.
+#[debug_handler(state = AppState)]
+pub async fn get_project_diff(
+    Path(account_id): Path<String>,
+    Query(DiffParams { from, to }): Query<DiffParams>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<ProjectDiff>, (StatusCode, String)> {
+    let mut args: HashMap<String, serde_json::Value> = sqlx::query!(
+        r#"
+        SELECT
+          transactions.hash,
+          transactions.args
+        FROM
+          transactions
+        WHERE
+          transactions.hash = ANY ($1)
+          AND transactions.method_name IN ('add_project', 'edit_project')
+          AND transactions.subject_id = $2
+        "#,
+        &[from.clone(), to.clone()][..],
+        account_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get project edits: {e}"),
+        )
+    })?
+    .into_iter()
+    .map(|row| (row.hash, row.args))
+    .collect();
+
+    let mut edit_args = |hash: &str| {
+        args.remove(hash).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Transaction {hash} isn't an edit of project {account_id}"),
+            )
+        })
+    };
+    let before = edit_args(&from)?;
+    // Comparing a transaction with itself finds no changes
+    let after = if from == to {
+        before.clone()
+    } else {
+        edit_args(&to)?
+    };
+
+    Ok(Json(ProjectDiff::new(from, to, before, after)))
+}
+
+pub const DEFAULT_RECENT_DAYS: u32 = 7;
+
+/// How far back recent projects can be looked up, in days.
+pub const MAX_RECENT_DAYS: u32 = 3_650;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RecentParams {
+    pub days: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, sqlx::FromRow)]
+pub struct RecentProject {
+    pub id: String,
+    /// When the project was last added or edited, in nanoseconds.
+    pub timestamp: i64,
+}
+
+/// Lists the projects that were added or edited in the last `days` days, most
+/// recently active first.
+#[debug_handler(state = AppState)]
+pub async fn get_recent_projects(
+    Query(params): Query<RecentParams>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<Vec<RecentProject>>, (StatusCode, String)> {
+    let days = params.days.unwrap_or(DEFAULT_RECENT_DAYS);
+    if days > MAX_RECENT_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("days can't be more than {MAX_RECENT_DAYS}"),
+        ));
+    }
+    let since = chrono::Utc::now()
+        .checked_sub_signed(chrono::Duration::days(days.into()))
+        .and_then(|since| since.timestamp_nanos_opt())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "days is out of range".to_string()))?;
+
+    let sql = format!(
+        r#"
+        SELECT
+          projects.id,
+          txs.timestamp
+        FROM
+          projects
+          JOIN (
+            SELECT
+              COALESCE(
+                transactions.args ->> 'account_id',
+                transactions.args ->> 'project_id'
+              ) AS account_id,
+              MAX(transactions.timestamp) AS timestamp
+            FROM
+              transactions
+            WHERE
+              transactions.method_name IN ('add_project', 'edit_project')
+            GROUP BY
+              COALESCE(
+                transactions.args ->> 'account_id',
+                transactions.args ->> 'project_id'
+              )
+          ) AS txs ON projects.id = txs.account_id
+          {REMOVALS_JOIN}
+        WHERE
+          txs.timestamp >= $1
+          AND {NOT_REMOVED}
+        ORDER BY
+          txs.timestamp DESC,
+          projects.id ASC
+        LIMIT
+          $2
+        "#
+    );
+
+    sqlx::query_as::<_, RecentProject>(&sql)
+        .bind(since)
+        .bind(params.limit.map(|limit| limit as i64))
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to get recent projects: {e}"),
+            )
+        })
+        .map(Json)
+}
+
+pub const DEFAULT_HALF_LIFE_DAYS: f64 = 30.0;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct RankedParams {
+    /// After how many days without edits a project's score is halved.
+    pub half_life_days: Option<f64>,
+    #[serde(default)]
+    pub include_deleted: bool,
+    pub from: Option<u32>,
+    pub limit: Option<u32>,
+}
+
+/// Lists projects by their completion decayed by the time since they were last added
+/// or edited, so a stale complete profile ranks below a fresh one that is a bit less
+/// complete. Projects without any transactions rank last.
+#[debug_handler(state = AppState)]
+pub async fn get_ranked_projects(
+    Query(params): Query<RankedParams>,
+    Query(EnvelopeParams { envelope }): Query<EnvelopeParams>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Response, (StatusCode, String)> {
+    let half_life_days = params.half_life_days.unwrap_or(DEFAULT_HALF_LIFE_DAYS);
+    if !half_life_days.is_finite() || half_life_days <= 0.0 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "half_life_days must be positive".to_string(),
+        ));
+    }
+    let half_life = half_life_days * 24.0 * 60.0 * 60.0 * 1_000_000_000.0;
+    let now = now_nanos();
+
+    let (join, _, _) = Sort::RecentDesc.get_order_by();
+    let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new(
+        r#"
+        SELECT
+          projects.id,
+          COUNT(*) OVER () AS total
+        FROM
+          projects
+        "#,
+    );
+    builder.push(join);
+    if !params.include_deleted {
+        builder.push(REMOVALS_JOIN);
+        builder.push(format!("WHERE {NOT_REMOVED}"));
+    }
+    builder.push(" ORDER BY COALESCE(projects.completion * power(0.5, GREATEST(");
+    builder.push_bind(now);
+    builder.push(" - txs.timestamp, 0) :: float8 / ");
+    builder.push_bind(half_life);
+    builder.push("), 0) DESC, projects.id ASC");
+
+    if let Some(limit) = params.limit {
+        builder.push(" LIMIT ");
+        builder.push_bind(limit as i32);
+    }
+
+    if let Some(from) = params.from {
+        builder.push(" OFFSET ");
+        builder.push_bind(from as i32);
+    }
+
+    let result = builder.build().fetch_all(&pool).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get ranked projects: {e}"),
+        )
+    })?;
+
+    let total = result.first().map(|r| r.get::<i64, _>("total") as u64);
+    let ids: Vec<String> = result.into_iter().map(|r| r.get("id")).collect();
+    let has_more = total
+        .is_some_and(|total| params.from.unwrap_or_default() as u64 + (ids.len() as u64) < total);
+
+    let page = Page::new(ids, total, params.from, params.limit, has_more);
+    Ok(listing_response(page, envelope))
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct IntegrationCount {
+    pub integration: String,
+    pub count: i64,
+}
+
+/// Lists the integration partners projects use with how many projects use each,
+/// most used first.
+#[debug_handler(state = AppState)]
+pub async fn get_integrations(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<IntegrationCount>>, Response> {
+    let mut builder = sqlx::QueryBuilder::<sqlx::Postgres>::new(
+        r#"
+        SELECT
+          projects.integration,
+          COUNT(*) AS count
+        FROM
+          projects
+        "#,
+    );
+    builder.push(REMOVALS_JOIN);
+    builder.push(format!(
+        r#"
+        WHERE
+          {NOT_REMOVED}
+          AND projects.integration IS NOT NULL
+          AND TRIM(projects.integration) != ''
+        GROUP BY
+          projects.integration
+        ORDER BY
+          count DESC,
+          projects.integration ASC
+        "#
+    ));
+
+    let mut db_tx = state.timed_transaction().await?;
+    let result = state
+        .timed(
+            builder.build().fetch_all(&mut db_tx),
+            "Failed to get integrations",
+        )
+        .await?;
+
+    Ok(Json(
+        result
+            .into_iter()
+            .map(|r| IntegrationCount {
+                integration: r.get("integration"),
+                count: r.get("count"),
+            })
+            .collect(),
+    ))
+}
+
+pub const DEFAULT_FULL_SIMILAR_LIMIT: u32 = 10;
+pub const DEFAULT_FULL_ACTIVITY_LIMIT: u32 = 20;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct FullParams {
+    pub similar_limit: Option<u32>,
+    pub activity_limit: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct FullProject {
+    pub project: serde_json::Value,
+    /// Whether the project is on the credits token's allowlist.
+    pub credits_registered: bool,
+    pub similar: Vec<String>,
+    pub activity: Vec<Transaction>,
+}
+
+/// Everything the project page needs in one round-trip: the project itself, its
+/// most similar projects and its latest transactions, queried in one timed transaction.
+#[debug_handler(state = AppState)]
+pub async fn get_full_project(
+    Path(account_id): Path<String>,
+    Query(params): Query<FullParams>,
+    State(state): State<AppState>,
+) -> Result<Json<FullProject>, Response> {
+    let _permit = state.expensive_query_permit().await?;
+
+    let similar_limit = params.similar_limit.unwrap_or(DEFAULT_FULL_SIMILAR_LIMIT);
+    let activity_limit = params.activity_limit.unwrap_or(DEFAULT_FULL_ACTIVITY_LIMIT);
+
+    // The queries share the transaction, so each one is bound by the query timeout
+    let mut db_tx = state.timed_transaction().await?;
+    let project = state
+        .timed(
+            sqlx::query!(
+                r#"
+                SELECT
+                  to_jsonb(projects) AS "project!",
+                  EXISTS (
+                    SELECT
+                      1
+                    FROM
+                      credits_registrations
+                    WHERE
+                      credits_registrations.account_id = projects.id
+                  ) AS "credits_registered!"
+                FROM
+                  projects
+                WHERE
+                  projects.id = $1
+                "#,
+                account_id
+            )
+            .fetch_optional(&mut db_tx),
+            "Failed to get project",
+        )
+        .await?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("Project {account_id} not found"),
+            )
+                .into_response()
+        })?;
+
+    let similar = state
+        .timed(
+            cached_similar_project_ids(
+                &mut db_tx,
+                &account_id,
+                state.similar_min_completion,
+                false,
+                None,
+                state.similarity_candidate_cap,
+                Some(similar_limit as i64),
+                None,
+            ),
+            "Failed to get similar projects",
+        )
+        .await?;
+
+    let activity = state
+        .timed(
+            sqlx::query_as!(
+                Transaction,
+                r#"
+                SELECT
+                  *
+                FROM
+                  transactions
+                WHERE
+                  transactions.subject_id = $1
+                ORDER BY
+                  transactions.timestamp DESC,
+                  transactions.id DESC
+                LIMIT
+                  $2
+                "#,
+                account_id,
+                activity_limit as i64
+            )
+            .fetch_all(&mut db_tx),
+            "Failed to get project activity",
+        )
+        .await?;
+
+    Ok(Json(FullProject {
+        project: project.project,
+        credits_registered: project.credits_registered,
+        similar,
+        activity,
+    }))
 }
 
 #[debug_handler(state = AppState)]
@@ -458,14 +1809,931 @@ async fn get_completion(
         )
     })?;
 
-    let avg = list.iter().map(|c| c.completion).sum::<f64>() / list.len() as f64;
-
-    Ok(Json(Completion { avg, list }))
+    Ok(Json(Completion::new(list)))
 }
 
 pub fn create_router() -> Router<AppState> {
     Router::new()
         .route("/", get(all_projects))
+        .route("/stream", get(stream_projects))
+        .route("/count", get(get_projects_count))
+        .route("/board", get(get_board))
         .route("/completion", get(get_completion))
+        .route("/names", post(get_names))
+        .route("/integrations", get(get_integrations))
+        .route("/recent", get(get_recent_projects))
+        .route("/ranked", get(get_ranked_projects))
         .route("/:account_id/similar", get(get_similar_projects))
+        .route("/:account_id/full", get(get_full_project))
+        .route(
+            "/:account_id/completion-history",
+            get(get_completion_history),
+        )
+        .route("/:account_id/diff", get(get_project_diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(items: &[&str]) -> Option<HashSet<String>> {
+        Some(items.iter().map(|item| item.to_string()).collect())
+    }
+
+    /// Inserts a project with every column empty except for the `fields` given, e.g.
+    /// `json!({ "name": "A" })`.
+    async fn insert_project(
+        executor: impl sqlx::PgExecutor<'_>,
+        id: &str,
+        fields: serde_json::Value,
+    ) {
+        let mut project = serde_json::json!({
+            "id": id,
+            "founders": [],
+            "team": {},
+            "why": "",
+            "integration": "",
+            "success_position": "",
+            "problem": "",
+            "vision": "",
+            "deck": "",
+            "white_paper": "",
+            "roadmap": "",
+            "team_deck": "",
+            "demo": "",
+            "tam": "",
+            "geo": "",
+            "verified": false,
+            "application": "",
+            "name": "",
+            "description": "",
+            "image": {},
+            "website": "",
+            "tagline": "",
+            "linktree": {},
+            "vertical": {},
+            "stage": "",
+            "userbase": 0,
+            "distribution": "",
+            "dev": "",
+            "product_type": [],
+            "company_size": 0,
+        });
+        project
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().cloned().unwrap_or_default());
+
+        sqlx::query(
+            r#"
+            INSERT INTO
+              projects (
+                id, founders, team, why, integration, success_position, problem, vision,
+                deck, white_paper, roadmap, team_deck, demo, tam, geo, verified,
+                application, name, description, image, website, tagline, linktree,
+                vertical, stage, userbase, distribution, dev, product_type, company_size
+              )
+            SELECT
+              id, founders, team, why, integration, success_position, problem, vision,
+              deck, white_paper, roadmap, team_deck, demo, tam, geo, verified,
+              application, name, description, image, website, tagline, linktree,
+              vertical, stage, userbase, distribution, dev, product_type, company_size
+            FROM
+              jsonb_populate_record(NULL :: projects, $1)
+            "#,
+        )
+        .bind(project)
+        .execute(executor)
+        .await
+        .unwrap();
+    }
+
+    /// Records a transaction calling `method_name` with the project's `account_id`.
+    async fn insert_transaction(
+        executor: impl sqlx::PgExecutor<'_>,
+        method_name: &str,
+        account_id: &str,
+        timestamp: i64,
+    ) {
+        sqlx::query(
+            r#"
+            INSERT INTO
+              transactions (hash, signer_id, method_name, args, log, block_hash, timestamp)
+            VALUES
+              (md5(random() :: text), $1, $2, $3, '', md5(random() :: text), $4)
+            "#,
+        )
+        .bind(account_id)
+        .bind(method_name)
+        .bind(serde_json::json!({ "account_id": account_id }))
+        .bind(timestamp)
+        .execute(executor)
+        .await
+        .unwrap();
+    }
+
+    /// Removes the projects and their transactions, so tests that have to commit
+    /// can run again.
+    async fn delete_projects(pool: &sqlx::PgPool, ids: &[&str]) {
+        let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        sqlx::query("DELETE FROM transactions WHERE args ->> 'account_id' = ANY ($1)")
+            .bind(&ids)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM projects WHERE id = ANY ($1)")
+            .bind(&ids)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    /// The ids [`all_projects`] lists for the `params`, sorted by name by default.
+    async fn listed(connection: &mut sqlx::PgConnection, params: Params) -> Vec<String> {
+        build_projects_query(params, Sort::NameAsc, false)
+            .build()
+            .fetch_all(connection)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.get("id"))
+            .collect()
+    }
+
+    /// Reads the whole body of a handler's response.
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        use axum::body::HttpBody;
+
+        let mut body = response.into_body();
+        let mut bytes = vec![];
+        while let Some(chunk) = body.data().await {
+            bytes.extend(chunk.unwrap());
+        }
+        bytes
+    }
+
+    /// Reads the JSON body of a handler's response.
+    async fn body_json<T: serde::de::DeserializeOwned>(response: Response) -> T {
+        serde_json::from_slice(&body_bytes(response).await).unwrap()
+    }
+
+    /// The SQL `apply_filters` builds on top of a bare project query.
+    fn filters_sql(params: &Params) -> String {
+        let mut builder = sqlx::QueryBuilder::new("SELECT projects.id FROM projects");
+        apply_filters(&mut builder, params);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn test_parse_size_range() {
+        assert_eq!(parse_size_range("1-5"), Ok((1, 5)));
+        assert_eq!(parse_size_range(" 2 - 2 "), Ok((2, 2)));
+        assert_eq!(parse_size_range("5-1"), Err("5-1".to_string()));
+        assert_eq!(parse_size_range("1-"), Err("1-".to_string()));
+        assert_eq!(parse_size_range("a-b"), Err("a-b".to_string()));
+        assert_eq!(parse_size_range("5"), Err("5".to_string()));
+    }
+
+    #[test]
+    fn test_sort_from_str() {
+        assert_eq!("timeasc".parse(), Ok(Sort::TimeAsc));
+        assert_eq!("Time_Desc".parse(), Ok(Sort::TimeDesc));
+        assert_eq!("name-asc".parse(), Ok(Sort::NameAsc));
+        assert_eq!("RECENTDESC".parse(), Ok(Sort::RecentDesc));
+        assert_eq!("oldest".parse(), Ok(Sort::TimeAsc));
+        assert_eq!("newest".parse(), Ok(Sort::TimeDesc));
+        assert_eq!("az".parse(), Ok(Sort::NameAsc));
+        assert_eq!("za".parse(), Ok(Sort::NameDesc));
+        assert_eq!(
+            "popular".parse::<Sort>(),
+            Err("unknown sort `popular`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_presence_condition() {
+        assert_eq!(
+            presence_condition("website"),
+            Some("NULLIF(TRIM(projects.website), '') IS NOT NULL")
+        );
+        assert_eq!(
+            presence_condition("founders"),
+            Some("COALESCE(array_length(projects.founders, 1), 0) > 0")
+        );
+        assert_eq!(presence_condition("name"), None);
+        assert_eq!(presence_condition(""), None);
+    }
+
+    #[test]
+    fn test_apply_filters_excludes_removed() {
+        assert_eq!(
+            filters_sql(&Params::default()),
+            format!("SELECT projects.id FROM projects{REMOVALS_JOIN} WHERE {NOT_REMOVED}")
+        );
+        assert_eq!(
+            filters_sql(&Params {
+                include_deleted: true,
+                ..Default::default()
+            }),
+            "SELECT projects.id FROM projects"
+        );
+    }
+
+    #[test]
+    fn test_apply_filters() {
+        // Only the first condition starts the `WHERE` clause
+        let sql = filters_sql(&Params {
+            include_deleted: true,
+            stage: set(&["idea"]),
+            dev: set(&["mainnet"]),
+            has: set(&["website"]),
+            ..Default::default()
+        });
+        assert_eq!(
+            sql,
+            "SELECT projects.id FROM projects \
+             WHERE projects.stage = ANY ($1)  \
+             AND projects.dev = ANY ($2)  \
+             AND  NULLIF(TRIM(projects.website), '') IS NOT NULL "
+        );
+
+        let sql = filters_sql(&Params {
+            vertical: set(&["defi"]),
+            vertical_match: VerticalMatch::All,
+            integration: set(&["native"]),
+            size: Some(SizeRanges {
+                ranges: HashSet::from([(1, 5)]),
+                invalid: vec![],
+            }),
+            distribution: set(&["open"]),
+            ids: set(&["a.near"]),
+            search: Some("horizon".to_string()),
+            ..Default::default()
+        });
+        let filters = sql
+            .split_once(NOT_REMOVED)
+            .map(|(_, filters)| filters)
+            .unwrap();
+        assert_eq!(filters.matches(" WHERE ").count(), 0);
+        assert_eq!(filters.matches(" AND ").count(), 7);
+        assert!(filters.starts_with(" AND projects.vertical ?& $1 "));
+        assert!(filters.contains("projects.integration = ANY ($2)"));
+        assert!(filters.contains("BETWEEN\n        $3 AND $4"));
+        assert!(filters.contains("projects.distribution = ANY ($5)"));
+        assert!(filters.contains("projects.id = ANY ($6)"));
+        assert!(filters.contains("unaccent($10)"));
+        assert!(!filters.contains("$11"));
+    }
+
+    #[test]
+    fn test_flatten_fields() {
+        let mut fields = BTreeMap::new();
+        flatten_fields(
+            None,
+            serde_json::json!({
+                "account_id": "a.near",
+                "project": { "name": "A", "links": { "website": "a.com" }, "tags": ["defi"] }
+            }),
+            &mut fields,
+        );
+
+        assert_eq!(
+            fields,
+            BTreeMap::from([
+                ("account_id".to_string(), serde_json::json!("a.near")),
+                (
+                    "project.links.website".to_string(),
+                    serde_json::json!("a.com")
+                ),
+                ("project.name".to_string(), serde_json::json!("A")),
+                ("project.tags".to_string(), serde_json::json!(["defi"])),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_project_diff() {
+        let diff = ProjectDiff::new(
+            "from".to_string(),
+            "to".to_string(),
+            serde_json::json!({
+                "account_id": "a.near",
+                "project": { "name": "A", "tagline": "Old", "tags": ["defi"] }
+            }),
+            serde_json::json!({
+                "account_id": "a.near",
+                "project": { "name": "A", "website": "a.com", "tags": ["defi", "nft"] }
+            }),
+        );
+
+        assert_eq!(diff.from, "from");
+        assert_eq!(diff.to, "to");
+        assert_eq!(
+            diff.added,
+            BTreeMap::from([("project.website".to_string(), serde_json::json!("a.com"))])
+        );
+        assert_eq!(
+            diff.removed,
+            BTreeMap::from([("project.tagline".to_string(), serde_json::json!("Old"))])
+        );
+        assert_eq!(
+            diff.changed,
+            BTreeMap::from([(
+                "project.tags".to_string(),
+                ChangedField {
+                    before: serde_json::json!(["defi"]),
+                    after: serde_json::json!(["defi", "nft"]),
+                }
+            )])
+        );
+
+        // Identical submissions have no differences
+        let args = serde_json::json!({ "project": { "name": "A" } });
+        let diff = ProjectDiff::new("from".to_string(), "to".to_string(), args.clone(), args);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_cached_similar_project_ids() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let defi = serde_json::json!({ "vertical": { "test-cached-similar": "" } });
+        insert_project(&mut db_tx, "cached-a.near", defi.clone()).await;
+        insert_project(&mut db_tx, "cached-b.near", defi.clone()).await;
+        sqlx::query("REFRESH MATERIALIZED VIEW project_similarity")
+            .execute(&mut db_tx)
+            .await
+            .unwrap();
+        // Added after the refresh, so only the live query knows about it
+        insert_project(&mut db_tx, "cached-c.near", defi).await;
+
+        let cap = DEFAULT_SIMILARITY_CANDIDATE_CAP;
+        let cached = cached_similar_project_ids(
+            &mut db_tx,
+            "cached-a.near",
+            0.0,
+            false,
+            None,
+            cap,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(cached, vec!["cached-b.near"]);
+
+        // A project missing from the view falls back to the live query
+        let live = cached_similar_project_ids(
+            &mut db_tx,
+            "cached-c.near",
+            0.0,
+            false,
+            None,
+            cap,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(live, vec!["cached-a.near", "cached-b.near"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_ranked_projects_decay() {
+        let state = AppState::new().await;
+        let (fresh, stale) = ("ranked-fresh.near", "ranked-stale.near");
+        delete_projects(&state.pool, &[fresh, stale]).await;
+
+        let day = 24 * 60 * 60 * 1_000_000_000;
+        insert_project(
+            &state.pool,
+            fresh,
+            serde_json::json!({ "name": "Fresh", "description": "Fresh" }),
+        )
+        .await;
+        insert_project(
+            &state.pool,
+            stale,
+            serde_json::json!({
+                "name": "Stale",
+                "description": "Stale",
+                "tagline": "Stale",
+                "website": "stale.com",
+                "stage": "idea",
+                "dev": "mainnet",
+                "geo": "Earth",
+            }),
+        )
+        .await;
+        insert_transaction(&state.pool, "add_project", fresh, now_nanos() - day).await;
+        insert_transaction(&state.pool, "add_project", stale, now_nanos() - 30 * day).await;
+
+        let ranked = |half_life_days| {
+            let state = state.clone();
+            async move {
+                let params = RankedParams {
+                    half_life_days: Some(half_life_days),
+                    ..Default::default()
+                };
+                let response = get_ranked_projects(
+                    Query(params),
+                    Query(EnvelopeParams::default()),
+                    State(state),
+                )
+                .await
+                .unwrap();
+                let ids: Vec<String> = body_json(response).await;
+                let position = |id| ids.iter().position(|other| other == id).unwrap();
+                position(fresh) < position(stale)
+            }
+        };
+
+        // A short half-life lets the fresh project outrank the more complete one,
+        // while a long one barely decays either of them
+        let fresh_first_short = ranked(1.0).await;
+        let fresh_first_long = ranked(10_000.0).await;
+        delete_projects(&state.pool, &[fresh, stale]).await;
+        assert!(fresh_first_short);
+        assert!(!fresh_first_long);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_stream_projects() {
+        let state = AppState::new().await;
+        let ids = ["stream-a.near", "stream-b.near", "stream-c.near"];
+        delete_projects(&state.pool, &ids).await;
+        for (id, name) in ids.iter().zip(["C", "B", "A"]) {
+            insert_project(&state.pool, id, serde_json::json!({ "name": name })).await;
+        }
+
+        let params = Params {
+            sort: Some(Sort::NameAsc),
+            ids: set(&ids),
+            ..Default::default()
+        };
+        let response = stream_projects(Query(params), State(state.clone()))
+            .await
+            .unwrap()
+            .into_response();
+        let bytes = body_bytes(response).await;
+        delete_projects(&state.pool, &ids).await;
+
+        // Every line is a JSON object, each project streamed once in the sort order
+        let streamed = String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|line| line["id"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, vec![ids[2], ids[1], ids[0]]);
+        assert_eq!(
+            streamed.iter().collect::<HashSet<_>>().len(),
+            streamed.len()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_deleted_projects() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let ids = ["deleted-a.near", "deleted-b.near"];
+        for id in ids {
+            insert_project(&mut db_tx, id, serde_json::json!({})).await;
+            insert_transaction(&mut db_tx, "add_project", id, 1).await;
+        }
+        insert_transaction(&mut db_tx, "remove_project", ids[1], 2).await;
+
+        let params = |include_deleted| Params {
+            ids: set(&ids),
+            include_deleted,
+            ..Default::default()
+        };
+        assert_eq!(listed(&mut db_tx, params(false)).await, vec![ids[0]]);
+        assert_eq!(listed(&mut db_tx, params(true)).await, ids);
+
+        // Adding the project again brings it back
+        insert_transaction(&mut db_tx, "add_project", ids[1], 3).await;
+        assert_eq!(listed(&mut db_tx, params(false)).await, ids);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_search_ignores_accents() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let (accented, plain) = ("search-accented.near", "search-plain.near");
+        insert_project(&mut db_tx, accented, serde_json::json!({ "name": "Café" })).await;
+        insert_project(&mut db_tx, plain, serde_json::json!({ "name": "Cafe" })).await;
+
+        let search = |search: &str| Params {
+            ids: set(&[accented, plain]),
+            search: Some(search.to_string()),
+            ..Default::default()
+        };
+        // Either spelling in any casing finds both projects
+        assert_eq!(
+            listed(&mut db_tx, search("cafe")).await,
+            vec![plain, accented]
+        );
+        assert_eq!(
+            listed(&mut db_tx, search("CAFÉ")).await,
+            vec![plain, accented]
+        );
+        assert!(listed(&mut db_tx, search("coffee")).await.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_get_integrations() {
+        let state = AppState::new().await;
+        let projects = [
+            ("integrations-1.near", "test-integration-b"),
+            ("integrations-2.near", "test-integration-a"),
+            ("integrations-3.near", "test-integration-c"),
+            ("integrations-4.near", "test-integration-c"),
+            ("integrations-5.near", "test-integration-a"),
+            ("integrations-6.near", "test-integration-b"),
+            ("integrations-7.near", "test-integration-a"),
+        ];
+        let ids = projects.map(|(id, _)| id);
+        delete_projects(&state.pool, &ids).await;
+        for (id, integration) in projects {
+            let fields = serde_json::json!({ "integration": integration });
+            insert_project(&state.pool, id, fields).await;
+            insert_transaction(&state.pool, "add_project", id, 1).await;
+        }
+        // Removed projects aren't counted, which leaves a and b tied
+        insert_transaction(&state.pool, "remove_project", ids[6], 2).await;
+        insert_transaction(&state.pool, "remove_project", ids[5], 2).await;
+        insert_transaction(&state.pool, "remove_project", ids[4], 2).await;
+
+        let Json(integrations) = get_integrations(State(state.clone())).await.unwrap();
+        delete_projects(&state.pool, &ids).await;
+
+        let counts = integrations
+            .into_iter()
+            .filter(|count| count.integration.starts_with("test-integration-"))
+            .map(|count| (count.integration, count.count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counts,
+            vec![
+                ("test-integration-c".to_string(), 2),
+                ("test-integration-a".to_string(), 1),
+                ("test-integration-b".to_string(), 1),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_get_names() {
+        let state = AppState::new().await;
+        let ids = ["names-1.near", "names-2.near"];
+        delete_projects(&state.pool, &ids).await;
+        for (id, name) in ids.iter().zip(["First", "Second"]) {
+            let fields = serde_json::json!({ "name": name, "tagline": format!("{name} tagline") });
+            insert_project(&state.pool, id, fields).await;
+        }
+
+        let Json(names) = get_names(
+            State(state.clone()),
+            Json(NamesBody {
+                ids: vec![
+                    "names-1.near".to_string(),
+                    "names-missing.near".to_string(),
+                    "names-2.near".to_string(),
+                ],
+            }),
+        )
+        .await
+        .unwrap();
+        delete_projects(&state.pool, &ids).await;
+
+        // Missing ids are left out of the map instead of failing the batch
+        assert_eq!(names.len(), 2);
+        assert!(!names.contains_key("names-missing.near"));
+        assert_eq!(names["names-1.near"].name, "First");
+        assert_eq!(names["names-1.near"].tagline, "First tagline");
+        assert_eq!(names["names-2.near"].name, "Second");
+
+        let (status, _) = get_names(
+            State(state),
+            Json(NamesBody {
+                ids: vec!["names-1.near".to_string(); MAX_NAMES + 1],
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_similar_project_ids_team_size() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let founders = |count: usize| -> Vec<String> {
+            (0..count).map(|i| format!("founder-{i}.near")).collect()
+        };
+        let vertical = serde_json::json!({ "test-team-size": "" });
+        let projects = [
+            ("team-size-target.near", 3),
+            ("team-size-a-large.near", 20),
+            ("team-size-b-alike.near", 4),
+        ];
+        for (id, size) in projects {
+            let fields = serde_json::json!({ "vertical": vertical, "founders": founders(size) });
+            insert_project(&mut db_tx, id, fields).await;
+        }
+
+        // Same vertical for both, so the closer team size decides despite the id
+        assert_eq!(
+            similar_project_ids(
+                &mut db_tx,
+                "team-size-target.near",
+                0.0,
+                false,
+                None,
+                DEFAULT_SIMILARITY_CANDIDATE_CAP,
+                None,
+                None,
+            )
+            .await
+            .unwrap(),
+            vec!["team-size-b-alike.near", "team-size-a-large.near"]
+        );
+        assert_eq!(
+            similar_project_ids(
+                &mut db_tx,
+                "team-size-target.near",
+                0.0,
+                false,
+                Some(2),
+                DEFAULT_SIMILARITY_CANDIDATE_CAP,
+                None,
+                None,
+            )
+            .await
+            .unwrap(),
+            vec!["team-size-b-alike.near"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_similar_project_ids_candidate_cap() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let vertical = serde_json::json!({ "test-candidate-cap": "" });
+        let fields = [
+            ("cap-target.near", serde_json::json!({ "stage": "idea" })),
+            // The best match, but the least complete candidate
+            ("cap-a.near", serde_json::json!({ "stage": "idea" })),
+            (
+                "cap-b.near",
+                serde_json::json!({ "name": "B", "tagline": "B" }),
+            ),
+            (
+                "cap-c.near",
+                serde_json::json!({ "name": "C", "tagline": "C" }),
+            ),
+        ];
+        for (id, mut fields) in fields {
+            fields["vertical"] = vertical.clone();
+            insert_project(&mut db_tx, id, fields).await;
+        }
+
+        assert_eq!(
+            similar_project_ids(
+                &mut db_tx,
+                "cap-target.near",
+                0.0,
+                false,
+                None,
+                3,
+                None,
+                None,
+            )
+            .await
+            .unwrap(),
+            vec!["cap-a.near", "cap-b.near", "cap-c.near"]
+        );
+        // Only the most complete candidates are scored
+        assert_eq!(
+            similar_project_ids(
+                &mut db_tx,
+                "cap-target.near",
+                0.0,
+                false,
+                None,
+                2,
+                None,
+                None,
+            )
+            .await
+            .unwrap(),
+            vec!["cap-b.near", "cap-c.near"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_ids_with_vertical() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let projects = [
+            ("ids-a.near", "A", "test-ids-defi"),
+            ("ids-b.near", "B", "test-ids-nft"),
+            ("ids-c.near", "C", "test-ids-defi"),
+            ("ids-d.near", "D", "test-ids-defi"),
+        ];
+        for (id, name, vertical) in projects {
+            let fields = serde_json::json!({ "name": name, "vertical": { vertical: "" } });
+            insert_project(&mut db_tx, id, fields).await;
+        }
+
+        // ids-b is requested but not in the vertical, ids-d is in it but not requested
+        let params = Params {
+            ids: set(&["ids-a.near", "ids-b.near", "ids-c.near"]),
+            vertical: set(&["test-ids-defi"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            listed(&mut db_tx, params).await,
+            vec!["ids-a.near", "ids-c.near"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_get_recent_projects() {
+        let state = AppState::new().await;
+        let ids = [
+            "recent-edited.near",
+            "recent-added.near",
+            "recent-old.near",
+            "recent-removed.near",
+        ];
+        delete_projects(&state.pool, &ids).await;
+
+        let (hour, day) = (60 * 60 * 1_000_000_000, 24 * 60 * 60 * 1_000_000_000);
+        let now = now_nanos();
+        for id in ids {
+            insert_project(&state.pool, id, serde_json::json!({})).await;
+        }
+        // Added long ago but edited just now, so it's the most recent one
+        insert_transaction(&state.pool, "add_project", ids[0], now - 20 * day).await;
+        insert_transaction(&state.pool, "edit_project", ids[0], now - hour).await;
+        insert_transaction(&state.pool, "add_project", ids[1], now - 3 * day).await;
+        insert_transaction(&state.pool, "add_project", ids[2], now - 10 * day).await;
+        insert_transaction(&state.pool, "add_project", ids[3], now - day).await;
+        insert_transaction(&state.pool, "remove_project", ids[3], now - hour).await;
+
+        let recent = |days| {
+            let state = state.clone();
+            async move {
+                let params = RecentParams {
+                    days,
+                    ..Default::default()
+                };
+                let Json(projects) = get_recent_projects(Query(params), State(state))
+                    .await
+                    .unwrap();
+                projects
+                    .into_iter()
+                    .map(|project| project.id)
+                    .filter(|id| id.starts_with("recent-"))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let last_week = recent(None).await;
+        let last_month = recent(Some(30)).await;
+        delete_projects(&state.pool, &ids).await;
+        assert_eq!(last_week, vec![ids[0], ids[1]]);
+        assert_eq!(last_month, vec![ids[0], ids[1], ids[2]]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_listing_total_matches_count() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let projects = [
+            (
+                "total-1.near",
+                serde_json::json!({
+                    "vertical": { "test-total-defi": "" },
+                    "stage": "idea",
+                    "name": "Horizon",
+                }),
+            ),
+            (
+                "total-2.near",
+                serde_json::json!({
+                    "vertical": { "test-total-defi": "", "test-total-nft": "" },
+                    "website": "total.com",
+                }),
+            ),
+            (
+                "total-3.near",
+                serde_json::json!({ "vertical": { "test-total-nft": "" }, "stage": "idea" }),
+            ),
+            (
+                "total-4.near",
+                serde_json::json!({ "vertical": { "test-total-defi": "" }, "stage": "idea" }),
+            ),
+        ];
+        for (id, fields) in projects {
+            insert_project(&mut db_tx, id, fields).await;
+        }
+        insert_transaction(&mut db_tx, "remove_project", "total-4.near", 1).await;
+
+        let both = set(&["test-total-defi", "test-total-nft"]);
+        let cases = [
+            (
+                Params {
+                    vertical: both.clone(),
+                    ..Default::default()
+                },
+                3,
+            ),
+            (
+                Params {
+                    vertical: both.clone(),
+                    include_deleted: true,
+                    ..Default::default()
+                },
+                4,
+            ),
+            (
+                Params {
+                    vertical: both.clone(),
+                    vertical_match: VerticalMatch::All,
+                    ..Default::default()
+                },
+                1,
+            ),
+            (
+                Params {
+                    vertical: both.clone(),
+                    stage: set(&["idea"]),
+                    ..Default::default()
+                },
+                2,
+            ),
+            (
+                Params {
+                    vertical: both.clone(),
+                    has: set(&["website"]),
+                    ..Default::default()
+                },
+                1,
+            ),
+            (
+                Params {
+                    vertical: both.clone(),
+                    search: Some("horizon".to_string()),
+                    ..Default::default()
+                },
+                1,
+            ),
+            (
+                Params {
+                    ids: set(&["total-1.near", "total-3.near", "total-4.near"]),
+                    stage: set(&["idea"]),
+                    ..Default::default()
+                },
+                2,
+            ),
+        ];
+        for (params, expected) in cases {
+            let count = filter_count(&mut db_tx, &params).await.unwrap();
+            // A one-item page still carries the total of every match
+            let params = Params {
+                limit: Some(1),
+                ..params
+            };
+            let total = build_projects_query(params, Sort::NameAsc, true)
+                .build()
+                .fetch_all(&mut db_tx)
+                .await
+                .unwrap()
+                .first()
+                .map_or(0, |row| row.get::<i64, _>("total") as u64);
+            assert_eq!((total, count), (expected, expected));
+        }
+    }
 }
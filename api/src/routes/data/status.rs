@@ -0,0 +1,113 @@
+use axum::{debug_handler, extract::State, routing::get, Json, Router};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{now_nanos, AppState};
+
+/// How fresh the indexed data is. Timestamps are in nanoseconds.
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq, Eq)]
+pub struct Status {
+    /// The highest block the indexer has processed.
+    pub block_height: Option<i64>,
+    pub block_timestamp: Option<i64>,
+    pub last_transaction_timestamp: Option<i64>,
+    /// How many whole seconds the processed block is behind the current time.
+    pub seconds_behind: Option<i64>,
+}
+
+/// Reports the indexer's progress from the shared database. Always answers `200`,
+/// even when the indexer is far behind, so clients can show how old the data is.
+#[debug_handler(state = AppState)]
+async fn get_status(
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<Status>, (StatusCode, String)> {
+    let status = read_status(&pool, now_nanos()).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get status: {e}"),
+        )
+    })?;
+
+    Ok(Json(status))
+}
+
+/// The indexer's progress as of `now` (in nanoseconds).
+async fn read_status(executor: impl sqlx::PgExecutor<'_>, now: i64) -> Result<Status, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+          (
+            SELECT
+              last_visited.height
+            FROM
+              last_visited
+            WHERE
+              last_visited.id = 1
+          ) AS block_height,
+          (
+            SELECT
+              last_visited.timestamp
+            FROM
+              last_visited
+            WHERE
+              last_visited.id = 1
+          ) AS block_timestamp,
+          (
+            SELECT
+              MAX(transactions.timestamp)
+            FROM
+              transactions
+          ) AS last_transaction_timestamp
+        "#,
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(Status {
+        block_height: row.block_height,
+        block_timestamp: row.block_timestamp,
+        last_transaction_timestamp: row.last_transaction_timestamp,
+        seconds_behind: row
+            .block_timestamp
+            .map(|timestamp| now.saturating_sub(timestamp).max(0) / 1_000_000_000),
+    })
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new().route("/", get(get_status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database at DATABASE_URL"]
+    async fn test_read_status() {
+        let state = AppState::new().await;
+        let mut db_tx = state.pool.begin().await.unwrap();
+
+        let now = now_nanos();
+        let block_timestamp = now - 90 * 1_000_000_000;
+        sqlx::query(
+            r#"
+            INSERT INTO last_visited (id, height, timestamp)
+            VALUES (1, 42, $1)
+            ON CONFLICT (id) DO UPDATE SET height = 42, timestamp = $1
+            "#,
+        )
+        .bind(block_timestamp)
+        .execute(&mut db_tx)
+        .await
+        .unwrap();
+
+        let status = read_status(&mut db_tx, now).await.unwrap();
+        assert_eq!(status.block_height, Some(42));
+        assert_eq!(status.block_timestamp, Some(block_timestamp));
+        assert_eq!(status.seconds_behind, Some(90));
+
+        // A block timestamp ahead of the clock isn't reported as negative lag
+        let status = read_status(&mut db_tx, block_timestamp - 1).await.unwrap();
+        assert_eq!(status.seconds_behind, Some(0));
+    }
+}
@@ -7,6 +7,7 @@ pub mod data;
 pub mod decrypt;
 pub mod encrypt;
 pub mod transactions;
+pub mod trends;
 
 pub fn create_router() -> Router<AppState> {
     Router::new()
@@ -15,4 +16,5 @@ pub fn create_router() -> Router<AppState> {
         .nest("/transactions", transactions::create_router())
         .nest("/atlas", atlas::create_router())
         .nest("/data", data::create_router())
+        .nest("/trends", trends::create_router())
 }
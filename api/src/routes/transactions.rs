@@ -1,6 +1,7 @@
 use axum::{
     debug_handler,
     extract::{Path, Query, State},
+    response::Response,
     routing::get,
     Json, Router,
 };
@@ -8,6 +9,7 @@ use chrono::prelude::*;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 
+use crate::routes::data::projects::{NOT_REMOVED, REMOVALS_JOIN};
 use crate::AppState;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -20,6 +22,8 @@ pub struct Transaction {
     pub log: String,
     pub block_hash: String,
     pub timestamp: i64,
+    pub args_decode_failed: bool,
+    pub subject_id: Option<String>,
 }
 
 #[debug_handler(state = AppState)]
@@ -78,11 +82,15 @@ where
                 .unwrap()
         });
 
-    let from = date.timestamp_nanos();
+    let nanos = |date: DateTime<Utc>| -> Result<i64, D::Error> {
+        date.timestamp_nanos_opt()
+            .ok_or_else(|| serde::de::Error::custom("Date is out of range"))
+    };
+    let from = nanos(date)?;
     let to = date
         .checked_add_signed(chrono::Duration::days(1))
-        .unwrap()
-        .timestamp_nanos();
+        .ok_or_else(|| serde::de::Error::custom("Date is out of range"))
+        .and_then(nanos)?;
     Ok(Some((from, to)))
 }
 
@@ -123,11 +131,9 @@ async fn get_created_entity_count(
             .unwrap()
             .with_nanosecond(0)
             .unwrap();
-        let from = date.timestamp_nanos();
-        let to = date
-            .checked_add_signed(chrono::Duration::days(1))
-            .unwrap()
-            .timestamp_nanos();
+        // Today is always in range
+        let from = date.timestamp_nanos_opt().unwrap_or_default();
+        let to = from + 24 * 60 * 60 * 1_000_000_000;
         (from, to)
     });
     sqlx::query_scalar!(
@@ -155,22 +161,29 @@ async fn get_created_entity_count(
     .map(|result| Json(result.unwrap_or(0)))
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-struct Stats {
-    projects: Option<i64>,
-    vendors: Option<i64>,
-    backers: Option<i64>,
-    requests: Option<i64>,
-    proposals: Option<i64>,
-    contributions: Option<i64>,
+#[derive(Deserialize, Serialize, Debug, sqlx::FromRow)]
+pub struct Stats {
+    pub projects: Option<i64>,
+    pub vendors: Option<i64>,
+    pub backers: Option<i64>,
+    pub requests: Option<i64>,
+    pub proposals: Option<i64>,
+    pub contributions: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+struct StatsParams {
+    #[serde(default)]
+    include_deleted: bool,
 }
 
 #[debug_handler(state = AppState)]
 async fn get_stats(
-    State(AppState { pool, .. }): State<AppState>,
-) -> Result<Json<Stats>, (StatusCode, String)> {
-    sqlx::query_as!(
-        Stats,
+    Query(StatsParams { include_deleted }): Query<StatsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Stats>, Response> {
+    let mut db_tx = state.timed_transaction().await?;
+    let sql = format!(
         r#"
         SELECT
           (
@@ -178,6 +191,10 @@ async fn get_stats(
               COUNT(*)
             FROM
               projects
+              {REMOVALS_JOIN}
+            WHERE
+              $1
+              OR {NOT_REMOVED}
           ) AS projects,
           (
             SELECT
@@ -209,17 +226,13 @@ async fn get_stats(
             FROM
               contributions
           ) AS contributions
-    "#,
-    )
-    .fetch_one(&pool)
-    .await
-    .map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to get projects: {e}"),
-        )
-    })
-    .map(Json)
+    "#
+    );
+    let query = sqlx::query_as::<_, Stats>(&sql)
+        .bind(include_deleted)
+        .fetch_one(&mut db_tx);
+
+    state.timed(query, "Failed to get stats").await.map(Json)
 }
 
 pub fn create_router() -> Router<AppState> {
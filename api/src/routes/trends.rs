@@ -0,0 +1,202 @@
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{now_nanos, AppState};
+
+pub const DEFAULT_WINDOW: &str = "30d";
+pub const DEFAULT_BUCKETS: u32 = 6;
+pub const MAX_BUCKETS: u32 = 100;
+
+/// Parses a window like `30d`, `4w` or `12h`, `None` if it's malformed or too long
+/// to be represented.
+pub fn parse_window(window: &str) -> Option<chrono::Duration> {
+    let window = window.trim();
+    let (index, unit) = window.char_indices().last()?;
+    let amount = window[..index]
+        .parse::<i64>()
+        .ok()
+        .filter(|amount| *amount > 0)?;
+    match unit {
+        'h' => chrono::Duration::try_hours(amount),
+        'd' => chrono::Duration::try_days(amount),
+        'w' => chrono::Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct TrendParams {
+    /// How far back to look, e.g. `30d`, `4w` or `12h`.
+    pub window: Option<String>,
+    /// How many equal buckets the window is split into.
+    pub buckets: Option<u32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerticalTrend {
+    pub vertical: String,
+    /// The number of projects added in each bucket, oldest first.
+    pub counts: Vec<i64>,
+}
+
+/// Timestamps are in nanoseconds.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerticalTrends {
+    /// When each bucket starts, the last one ends at `to`.
+    pub buckets: Vec<i64>,
+    pub to: i64,
+    pub verticals: Vec<VerticalTrend>,
+}
+
+/// Counts the projects added to each vertical per time bucket of the window, based
+/// on when their `add_project` transaction was indexed. Every known vertical gets a
+/// count for every bucket, including the empty ones.
+#[debug_handler(state = AppState)]
+async fn get_vertical_trends(
+    Query(params): Query<TrendParams>,
+    State(AppState { pool, .. }): State<AppState>,
+) -> Result<Json<VerticalTrends>, (StatusCode, String)> {
+    let window = params.window.as_deref().unwrap_or(DEFAULT_WINDOW);
+    let window = parse_window(window).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid window `{window}`, expected e.g. 30d, 4w or 12h"),
+        )
+    })?;
+    let buckets = params.buckets.unwrap_or(DEFAULT_BUCKETS);
+    if !(1..=MAX_BUCKETS).contains(&buckets) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("buckets must be between 1 and {MAX_BUCKETS}"),
+        ));
+    }
+
+    let to = now_nanos();
+    let from = to - window.num_nanoseconds().unwrap_or(i64::MAX).min(to);
+
+    let rows = sqlx::query!(
+        r#"
+        WITH added AS (
+          SELECT
+            transactions.args ->> 'account_id' AS account_id,
+            MIN(transactions.timestamp) AS timestamp
+          FROM
+            transactions
+          WHERE
+            transactions.method_name = 'add_project'
+          GROUP BY
+            transactions.args ->> 'account_id'
+        ),
+        counts AS (
+          SELECT
+            v AS vertical,
+            LEAST(
+              width_bucket(
+                added.timestamp :: float8,
+                $1 :: bigint :: float8,
+                $2 :: bigint :: float8,
+                $3
+              ),
+              $3
+            ) AS bucket,
+            COUNT(*) AS count
+          FROM
+            added
+            JOIN projects ON projects.id = added.account_id,
+            jsonb_object_keys(projects.vertical) AS v
+          WHERE
+            added.timestamp BETWEEN $1 AND $2
+          GROUP BY
+            1,
+            2
+        ),
+        verticals AS (
+          SELECT
+            DISTINCT jsonb_object_keys(projects.vertical) AS vertical
+          FROM
+            projects
+          WHERE
+            jsonb_typeof(projects.vertical) = 'object'
+        )
+        SELECT
+          verticals.vertical AS "vertical!",
+          COALESCE(counts.count, 0) AS "count!"
+        FROM
+          verticals
+          CROSS JOIN generate_series(1, $3) AS buckets(bucket)
+          LEFT JOIN counts ON counts.vertical = verticals.vertical
+          AND counts.bucket = buckets.bucket
+        WHERE
+          verticals.vertical != ''
+        ORDER BY
+          verticals.vertical ASC,
+          buckets.bucket ASC
+        "#,
+        from,
+        to,
+        buckets as i32,
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get vertical trends: {e}"),
+        )
+    })?;
+
+    let mut verticals: Vec<VerticalTrend> = vec![];
+    for row in rows {
+        match verticals.last_mut() {
+            Some(trend) if trend.vertical == row.vertical => trend.counts.push(row.count),
+            _ => verticals.push(VerticalTrend {
+                vertical: row.vertical,
+                counts: vec![row.count],
+            }),
+        }
+    }
+
+    let bucket_size = (to - from) / buckets as i64;
+
+    Ok(Json(VerticalTrends {
+        buckets: (0..buckets as i64)
+            .map(|bucket| from + bucket * bucket_size)
+            .collect(),
+        to,
+        verticals,
+    }))
+}
+
+pub fn create_router() -> Router<AppState> {
+    Router::new().route("/verticals", get(get_vertical_trends))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("12h"), Some(chrono::Duration::hours(12)));
+        assert_eq!(parse_window(" 30d "), Some(chrono::Duration::days(30)));
+        assert_eq!(parse_window("4w"), Some(chrono::Duration::weeks(4)));
+    }
+
+    #[test]
+    fn test_parse_window_rejects_malformed() {
+        assert_eq!(parse_window(""), None);
+        assert_eq!(parse_window("d"), None);
+        assert_eq!(parse_window("0d"), None);
+        assert_eq!(parse_window("-3d"), None);
+        assert_eq!(parse_window("3m"), None);
+        assert_eq!(parse_window("3µ"), None);
+        assert_eq!(parse_window("µ"), None);
+        assert_eq!(parse_window("999999999999999w"), None);
+    }
+}
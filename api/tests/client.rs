@@ -0,0 +1,153 @@
+#![cfg(feature = "client")]
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use api::client::HorizonClient;
+use api::routes::data::projects::{FullProject, Params, SizeRanges, Sort, VerticalMatch};
+use api::routes::transactions::Stats;
+use api::AppState;
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+
+/// The listing params the server received last.
+type Received = Arc<Mutex<Option<Params>>>;
+
+async fn list_projects(
+    State(received): State<Received>,
+    Query(params): Query<Params>,
+) -> Json<Vec<String>> {
+    *received.lock().unwrap() = Some(params);
+    Json(vec!["a.near".to_string(), "b.near".to_string()])
+}
+
+async fn full_project(Path(account_id): Path<String>) -> Json<FullProject> {
+    Json(FullProject {
+        project: serde_json::json!({ "id": account_id }),
+        credits_registered: true,
+        similar: vec!["b.near".to_string()],
+        activity: vec![],
+    })
+}
+
+async fn similar(Path(account_id): Path<String>) -> Json<Vec<String>> {
+    Json(vec![format!("similar-to-{account_id}")])
+}
+
+async fn stats() -> Json<Stats> {
+    Json(Stats {
+        projects: Some(3),
+        vendors: Some(2),
+        backers: None,
+        requests: Some(1),
+        proposals: Some(0),
+        contributions: None,
+    })
+}
+
+/// Binds the router on an ephemeral port, returning the address it listens on.
+fn serve(router: Router) -> SocketAddr {
+    let server =
+        axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(router.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+fn set(items: &[&str]) -> Option<HashSet<String>> {
+    Some(items.iter().map(|item| item.to_string()).collect())
+}
+
+#[tokio::test]
+async fn test_client_round_trip() {
+    let received = Received::default();
+    let addr = serve(
+        Router::new()
+            .route("/data/projects", get(list_projects))
+            .route("/data/projects/:account_id/full", get(full_project))
+            .route("/data/projects/:account_id/similar", get(similar))
+            .route("/transactions/stats", get(stats))
+            .with_state(received.clone()),
+    );
+    let client = HorizonClient::new(&format!("http://{addr}/"));
+
+    let ids = client
+        .list_projects(&Params {
+            sort: Some(Sort::NameDesc),
+            vertical: set(&["defi", "nft"]),
+            vertical_match: VerticalMatch::All,
+            stage: set(&["idea"]),
+            size: Some(SizeRanges {
+                ranges: HashSet::from([(1, 5)]),
+                invalid: vec![],
+            }),
+            has: set(&["website"]),
+            from: Some(10),
+            limit: Some(5),
+            search: Some("horizon".to_string()),
+            include_deleted: true,
+            strict: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(ids, vec!["a.near", "b.near"]);
+
+    // The server parses the params back into what the client was given
+    let params = received.lock().unwrap().take().unwrap();
+    assert_eq!(params.sort, Some(Sort::NameDesc));
+    assert_eq!(params.vertical, set(&["defi", "nft"]));
+    assert!(matches!(params.vertical_match, VerticalMatch::All));
+    assert_eq!(params.stage, set(&["idea"]));
+    assert_eq!(params.integration, None);
+    assert_eq!(
+        params.size.map(|size| size.ranges),
+        Some(HashSet::from([(1, 5)]))
+    );
+    assert_eq!(params.has, set(&["website"]));
+    assert_eq!(params.from, Some(10));
+    assert_eq!(params.limit, Some(5));
+    assert_eq!(params.search.as_deref(), Some("horizon"));
+    assert!(params.include_deleted);
+    assert!(params.strict);
+
+    let project = client.get_project("a.near").await.unwrap();
+    assert_eq!(project.project["id"], "a.near");
+    assert!(project.credits_registered);
+    assert_eq!(project.similar, vec!["b.near"]);
+
+    assert_eq!(
+        client.similar("a.near").await.unwrap(),
+        vec!["similar-to-a.near"]
+    );
+
+    let stats = client.stats().await.unwrap();
+    assert_eq!(stats.projects, Some(3));
+    assert_eq!(stats.backers, None);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn test_client_against_api() {
+    let addr = serve(api::routes::create_router().with_state(AppState::new().await));
+    let client = HorizonClient::new(&format!("http://{addr}"));
+
+    let ids = client
+        .list_projects(&Params {
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(ids.len() <= 1);
+
+    client.stats().await.unwrap();
+
+    if let Some(id) = ids.first() {
+        let project = client.get_project(id).await.unwrap();
+        assert_eq!(project.project["id"], id.as_str());
+        client.similar(id).await.unwrap();
+    }
+}
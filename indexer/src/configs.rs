@@ -1,9 +1,276 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+use indexer::normalize_suffix;
+use near_lake_framework::near_indexer_primitives::types::AccountId;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::EnvFilter;
 
-pub(crate) fn init_logging() {
-    let env_filter = EnvFilter::new("near_lake_framework=info");
-    tracing_subscriber::fmt::Subscriber::builder()
+/// Command line options for the indexer.
+#[derive(Parser, Debug)]
+#[clap(version, about)]
+pub(crate) struct Opts {
+    /// Comma separated list of account ids to watch
+    #[clap(long, env = "ACCOUNTS", default_value = "")]
+    pub accounts: String,
+    /// File with one account id to watch per line, lines starting with `#` are ignored
+    #[clap(long)]
+    pub accounts_file: Option<PathBuf>,
+    /// Comma separated list of suffixes, receivers ending with one of them are watched as well
+    #[clap(long, value_delimiter = ',')]
+    pub account_suffixes: Vec<String>,
+    /// Comma separated list of arg keys tried in order to find the subject of a transaction
+    #[clap(long, value_delimiter = ',', default_value = "account_id,project_id")]
+    pub arg_keys: Vec<String>,
+    /// Log a warning when the indexer falls this many seconds behind the chain
+    #[clap(long, default_value_t = 300)]
+    pub lag_warn_threshold: u64,
+    /// Address the `/metrics` endpoint is served on
+    #[clap(long, env = "METRICS_ADDR", default_value = "0.0.0.0:9090")]
+    pub metrics_addr: SocketAddr,
+    /// RPC used to find the latest final block for the blocks behind metric
+    #[clap(long, env = "RPC_URL", default_value = "https://rpc.mainnet.near.org")]
+    pub rpc_url: String,
+    /// How often the latest final block is fetched, in seconds
+    #[clap(long, default_value_t = 10)]
+    pub final_block_poll_interval: u64,
+    /// How many blocks before the last visited one are checked for gaps on startup,
+    /// older processed blocks are pruned
+    #[clap(long, default_value_t = 10_000)]
+    pub gap_scan_range: u64,
+    /// How many processed blocks can wait to be written before reading new blocks pauses
+    #[clap(long, default_value_t = 64)]
+    pub write_queue_capacity: usize,
+    /// Where the indexed transactions are written to
+    #[clap(long, value_enum, default_value_t = Sink::Postgres)]
+    pub sink: Sink,
+    /// Redis connection url, required by the `redis` sink
+    #[clap(long, env = "REDIS_URL")]
+    pub redis_url: Option<String>,
+    /// Redis stream the `redis` sink publishes transactions to
+    #[clap(long, default_value = "horizon:transactions")]
+    pub redis_stream: String,
+    /// Comma separated list of token contracts whose NEP-297 events are stored, they
+    /// are watched as well
+    #[clap(long, env = "TOKEN_ACCOUNTS", default_value = "")]
+    pub token_accounts: String,
+    /// Log output format, the verbosity is controlled by `RUST_LOG`
+    #[clap(long, value_enum, env = "LOG_FORMAT", default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+/// The available log output formats.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    Text,
+    Json,
+}
+
+/// The available transaction sinks.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Sink {
+    Postgres,
+    Redis,
+}
+
+impl Opts {
+    /// Collects the watched accounts from both `--accounts` and `--accounts-file`.
+    pub fn watching_list(&self) -> Result<Vec<AccountId>, String> {
+        let mut watching_list =
+            parse_account_list(&self.accounts).map_err(|e| format!("Invalid --accounts: {e}"))?;
+
+        if let Some(path) = &self.accounts_file {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+            let accounts = parse_accounts_file(&contents)
+                .map_err(|e| format!("Invalid accounts file {}: {e}", path.display()))?;
+            for account_id in accounts {
+                if !watching_list.contains(&account_id) {
+                    watching_list.push(account_id);
+                }
+            }
+        }
+
+        Ok(watching_list)
+    }
+
+    /// The token contracts from `--token-accounts`.
+    pub fn token_accounts(&self) -> Result<Vec<AccountId>, String> {
+        parse_account_list(&self.token_accounts)
+            .map_err(|e| format!("Invalid --token-accounts: {e}"))
+    }
+
+    /// The normalized `--account-suffixes`.
+    pub fn watching_suffixes(&self) -> Vec<String> {
+        self.account_suffixes
+            .iter()
+            .filter(|suffix| !suffix.trim().is_empty())
+            .map(|suffix| normalize_suffix(suffix))
+            .collect()
+    }
+}
+
+/// Exits with the usage error clap reports for the options it validates itself.
+pub(crate) fn exit_invalid(message: String) -> ! {
+    Opts::command()
+        .error(ErrorKind::ValueValidation, message)
+        .exit()
+}
+
+/// Parses a comma separated list of account ids, trimming each entry and skipping
+/// empty ones. Every invalid entry is reported along with its position in the list.
+pub(crate) fn parse_account_list(list: &str) -> Result<Vec<AccountId>, String> {
+    collect_account_ids(
+        list.split(',')
+            .enumerate()
+            .map(|(index, entry)| (format!("entry {}", index + 1), entry.trim()))
+            .filter(|(_, entry)| !entry.is_empty()),
+    )
+}
+
+/// Parses the contents of an accounts file, skipping blank lines and `#` comments.
+/// Every invalid line is reported.
+pub(crate) fn parse_accounts_file(contents: &str) -> Result<Vec<AccountId>, String> {
+    collect_account_ids(contents.lines().enumerate().filter_map(|(index, line)| {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        (!line.is_empty()).then(|| (format!("line {}", index + 1), line))
+    }))
+}
+
+/// Parses the account ids, collecting the errors of all the invalid ones instead of
+/// stopping at the first. Each id comes with where it was found for the errors.
+fn collect_account_ids<'a>(
+    entries: impl Iterator<Item = (String, &'a str)>,
+) -> Result<Vec<AccountId>, String> {
+    let mut account_ids = vec![];
+    let mut errors = vec![];
+
+    for (position, entry) in entries {
+        match AccountId::from_str(entry) {
+            Ok(account_id) => account_ids.push(account_id),
+            Err(e) => errors.push(format!("{position}: invalid account id `{entry}`: {e}")),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(account_ids)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Sets up logging to stderr, see [`log_subscriber`].
+pub(crate) fn init_logging(format: LogFormat) {
+    log_subscriber(format, std::io::stderr).init();
+}
+
+/// Logs to `writer`, filtered by `RUST_LOG` when it's set. JSON logs include the
+/// fields of the current span (e.g. the block height).
+fn log_subscriber<W>(format: LogFormat, writer: W) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let env_filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("near_lake_framework=info,indexer=info"));
+    let builder = tracing_subscriber::fmt::Subscriber::builder()
         .with_env_filter(env_filter)
-        .with_writer(std::io::stderr)
-        .init();
+        .with_writer(writer);
+
+    match format {
+        LogFormat::Text => Box::new(builder.compact().finish()),
+        LogFormat::Json => Box::new(builder.json().with_current_span(true).finish()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{info, info_span};
+
+    use super::*;
+
+    /// A writer keeping everything written to it in memory.
+    #[derive(Clone, Default)]
+    struct CapturingWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for CapturingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'writer> MakeWriter<'writer> for CapturingWriter {
+        type Writer = Self;
+
+        fn make_writer(&'writer self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_parse_accounts_file() {
+        let contents = "# Watched contracts\n\nhorizon.near\n  app.horizon.near  # the app\n\n";
+        assert_eq!(
+            parse_accounts_file(contents),
+            Ok(vec![
+                "horizon.near".parse().unwrap(),
+                "app.horizon.near".parse().unwrap()
+            ])
+        );
+        assert_eq!(parse_accounts_file(""), Ok(vec![]));
+
+        // The invalid line is reported by its number in the file
+        let error = parse_accounts_file("horizon.near\n# comment\nNot Valid\n").unwrap_err();
+        assert!(error.starts_with("line 3: invalid account id `Not Valid`"));
+    }
+
+    #[test]
+    fn test_parse_account_list() {
+        assert_eq!(
+            parse_account_list(" horizon.near,, app.horizon.near "),
+            Ok(vec![
+                "horizon.near".parse().unwrap(),
+                "app.horizon.near".parse().unwrap()
+            ])
+        );
+
+        // Both bad ids are reported together, by their position in the list
+        let error = parse_account_list("Bad,horizon.near,also bad").unwrap_err();
+        let errors = error.split("; ").collect::<Vec<_>>();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("entry 1: invalid account id `Bad`"));
+        assert!(errors[1].starts_with("entry 3: invalid account id `also bad`"));
+    }
+
+    #[test]
+    fn test_json_logs() {
+        let writer = CapturingWriter::default();
+
+        tracing::subscriber::with_default(log_subscriber(LogFormat::Json, writer.clone()), || {
+            info_span!("block", component = "indexer", height = 42_u64)
+                .in_scope(|| info!("Processed block"));
+        });
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        let lines = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("Invalid JSON log"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["fields"]["message"], "Processed block");
+        assert_eq!(lines[0]["span"]["component"], "indexer");
+        assert_eq!(lines[0]["span"]["height"], 42);
+    }
 }
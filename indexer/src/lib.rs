@@ -1,17 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use base64::Engine as _;
 use itertools::Itertools;
 use near_lake_framework::near_indexer_primitives::{
     self, views::BlockView, CryptoHash, IndexerExecutionOutcomeWithReceipt, StreamerMessage,
 };
-use tracing::info;
+use tracing::warn;
 
+pub mod metrics;
+pub mod sink;
 pub mod store;
 
 pub fn collect_transactions(
     message: &StreamerMessage,
     watching_list: &[near_indexer_primitives::types::AccountId],
+    watching_suffixes: &[String],
 ) -> HashMap<CryptoHash, CryptoHash> {
     message
         .shards
@@ -23,19 +28,20 @@ pub fn collect_transactions(
             chunk
                 .transactions
                 .iter()
-                .filter_map(|transaction| {
-                    is_tx_receiver_watched(transaction, watching_list).then(|| {
-                        (
-                            *transaction
-                                .outcome
-                                .execution_outcome
-                                .outcome
-                                .receipt_ids
-                                .first()
-                                .expect("`receipt_ids` must contain one Receipt Id"),
-                            transaction.transaction.hash,
-                        )
-                    })
+                .filter(|transaction| {
+                    is_tx_receiver_watched(transaction, watching_list, watching_suffixes)
+                })
+                .map(|transaction| {
+                    (
+                        *transaction
+                            .outcome
+                            .execution_outcome
+                            .outcome
+                            .receipt_ids
+                            .first()
+                            .expect("`receipt_ids` must contain one Receipt Id"),
+                        transaction.transaction.hash,
+                    )
                 })
                 .collect()
         })
@@ -63,22 +69,71 @@ pub fn filter_outcomes(
         .collect()
 }
 
+/// Decodes function call args into JSON by trying each of the `engines` in order and
+/// then the args as raw JSON. When nothing works the args are kept as a lossy string
+/// and the returned flag is set so the transaction isn't lost.
+pub fn decode_args(
+    args: &[u8],
+    engines: &[base64::engine::GeneralPurpose],
+) -> (serde_json::Value, bool) {
+    let decoded = engines
+        .iter()
+        .filter_map(|engine| engine.decode(args).ok())
+        .find_map(|decoded| serde_json::from_slice::<serde_json::Value>(&decoded).ok());
+
+    if let Some(args_json) = decoded {
+        return (args_json, false);
+    }
+
+    if let Ok(args_json) = serde_json::from_slice::<serde_json::Value>(args) {
+        return (args_json, false);
+    }
+
+    (
+        serde_json::Value::String(String::from_utf8_lossy(args).into_owned()),
+        true,
+    )
+}
+
+/// Extracts the primary subject of a transaction (usually the account it acts on) by
+/// trying each of the `arg_keys` in order. Nested keys can be separated by a `.`
+/// (e.g. `proposal.vendor_id`).
+pub fn extract_subject(args: &serde_json::Value, arg_keys: &[String]) -> Option<String> {
+    arg_keys.iter().find_map(|key| {
+        key.split('.')
+            .try_fold(args, |value, part| value.get(part))
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    })
+}
+
+/// Extracts the function calls of the outcome, along with the events it logged when
+/// the receiver is one of the `token_accounts`.
 pub fn process_outcome(
     execution_outcome: IndexerExecutionOutcomeWithReceipt,
     tx_hash: CryptoHash,
-    engine: &base64::engine::GeneralPurpose,
+    engines: &[base64::engine::GeneralPurpose],
+    arg_keys: &[String],
+    token_accounts: &[near_indexer_primitives::types::AccountId],
     block: &BlockView,
-) -> Vec<store::Transaction> {
+) -> (Vec<store::Transaction>, Vec<store::TokenEvent>) {
+    let receiver_id = execution_outcome.receipt.receiver_id;
     let near_indexer_primitives::views::ReceiptEnumView::Action {
         signer_id, actions, ..
     } = execution_outcome.receipt.receipt else {
-        return vec![];
+        return (vec![], vec![]);
     };
     let logs = execution_outcome.execution_outcome.outcome.logs;
     let block_hash = block.header.hash;
     let timestamp = block.header.timestamp;
 
-    actions.into_iter().enumerate().filter_map(|(index, action)| {
+    let events = if token_accounts.contains(&receiver_id) {
+        parse_token_events(&logs, &receiver_id, tx_hash, block)
+    } else {
+        vec![]
+    };
+
+    let transactions = actions.into_iter().enumerate().filter_map(|(index, action)| {
         let log = logs
             .get(index)
             .map(|log| log.as_str())
@@ -87,29 +142,514 @@ pub fn process_outcome(
             eprintln!("Not a function call");
             return None;
         };
-        let decoded_args = engine.decode(args.clone()).unwrap_or({
-            info!("Can't decode args, possibly already decoded");
-            args
-        });
-        let Ok(args_json) = serde_json::from_slice::<serde_json::Value>(&decoded_args) else {
-            eprintln!("Can't parse args");
-            return None;
-        };
+        let (args_json, args_decode_failed) = decode_args(&args, engines);
+        if args_decode_failed {
+            warn!(%tx_hash, %method_name, "Can't decode args, storing them as is");
+        }
+        let subject_id = extract_subject(&args_json, arg_keys);
         Some(store::Transaction::new(
             tx_hash,
             signer_id.clone(),
             method_name,
             args_json,
+            args_decode_failed,
+            subject_id,
             log.to_string(),
             block_hash,
             timestamp,
         ))
-    }).collect()
+    }).collect();
+
+    (transactions, events)
 }
 
+/// The prefix of NEP-297 event log lines.
+pub const EVENT_LOG_PREFIX: &str = "EVENT_JSON:";
+
+/// Parses the NEP-297 events in the logs of a token contract. An event can carry
+/// several items in its `data` (e.g. a batch of transfers), each of them is returned
+/// as a separate event. Lines that aren't valid events are skipped.
+pub fn parse_token_events(
+    logs: &[String],
+    contract_id: &near_indexer_primitives::types::AccountId,
+    tx_hash: CryptoHash,
+    block: &BlockView,
+) -> Vec<store::TokenEvent> {
+    logs.iter()
+        .filter_map(|log| log.strip_prefix(EVENT_LOG_PREFIX))
+        .filter_map(|json| {
+            serde_json::from_str::<serde_json::Value>(json)
+                .map_err(|error| warn!(%tx_hash, %error, "Can't parse event log, skipping it"))
+                .ok()
+        })
+        .flat_map(|mut event| {
+            let standard = event["standard"].as_str().unwrap_or_default().to_string();
+            let name = event["event"].as_str().unwrap_or_default().to_string();
+            let items = match event["data"].take() {
+                serde_json::Value::Array(items) => items,
+                serde_json::Value::Null => vec![],
+                item => vec![item],
+            };
+            items.into_iter().map(move |data| {
+                store::TokenEvent::new(
+                    tx_hash,
+                    contract_id.clone(),
+                    standard.clone(),
+                    name.clone(),
+                    data,
+                    block.header.hash,
+                    block.header.timestamp,
+                )
+            })
+        })
+        .filter(|event| !event.event.is_empty())
+        .collect()
+}
+
+/// Checks whether the receiver is one of the watched accounts or a subaccount of
+/// one of the watched suffixes (which are expected to start with a `.`).
 pub fn is_tx_receiver_watched(
     tx: &near_indexer_primitives::IndexerTransactionWithOutcome,
     watching_list: &[near_indexer_primitives::types::AccountId],
+    watching_suffixes: &[String],
 ) -> bool {
-    watching_list.contains(&tx.transaction.receiver_id)
+    let receiver_id = &tx.transaction.receiver_id;
+    watching_list.contains(receiver_id)
+        || watching_suffixes
+            .iter()
+            .any(|suffix| receiver_id.as_str().ends_with(suffix.as_str()))
+}
+
+/// Normalizes an account suffix so it only matches on an account id boundary,
+/// e.g. `horizon.near` becomes `.horizon.near` which doesn't match `apphorizon.near`.
+pub fn normalize_suffix(suffix: &str) -> String {
+    format!(".{}", suffix.trim().trim_start_matches('.'))
+}
+
+/// Computes how many whole seconds the block (timestamp in nanoseconds) is behind `now`.
+pub fn seconds_behind(block_timestamp: u64, now: SystemTime) -> u64 {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is before UNIX epoch")
+        .as_nanos() as u64;
+    now.saturating_sub(block_timestamp) / 1_000_000_000
+}
+
+/// Finds the heights that were never processed between processed blocks. `blocks` are
+/// `(height, prev_height)` pairs sorted by height. Since the chain can skip heights,
+/// a gap is only reported when a block's previous block isn't the one processed
+/// right before it.
+pub fn find_gaps(blocks: &[(u64, Option<u64>)]) -> Vec<RangeInclusive<u64>> {
+    blocks
+        .windows(2)
+        .filter_map(|pair| {
+            let (previous, _) = pair[0];
+            match pair[1] {
+                (_, Some(prev_height)) if prev_height > previous => {
+                    Some(previous + 1..=prev_height)
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Where the indexer resumes after a restart, see [`plan_resume`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resume {
+    /// The height streaming restarts from.
+    pub start_block_height: u64,
+    /// The gaps found in the processed blocks, see [`find_gaps`].
+    pub gaps: Vec<RangeInclusive<u64>>,
+    /// The blocks from `start_block_height` on that were already processed and are
+    /// skipped.
+    pub processed: HashSet<u64>,
+}
+
+impl Resume {
+    /// The heights that are processed again, the ones in the gaps.
+    pub fn reprocessed(&self) -> Vec<u64> {
+        self.gaps
+            .iter()
+            .cloned()
+            .flatten()
+            .filter(|height| !self.processed.contains(height))
+            .collect()
+    }
+}
+
+/// Restarts from the earliest gap in the processed `blocks` (`(height, prev_height)`
+/// pairs sorted by height), or from `last_visited` when there are none. Blocks that
+/// were already processed from there on are skipped.
+pub fn plan_resume(last_visited: u64, blocks: &[(u64, Option<u64>)]) -> Resume {
+    let gaps = find_gaps(blocks);
+    let start_block_height = gaps.first().map_or(last_visited, |gap| *gap.start());
+    let processed = blocks
+        .iter()
+        .map(|(height, _)| *height)
+        .filter(|height| *height >= start_block_height)
+        .collect();
+
+    Resume {
+        start_block_height,
+        gaps,
+        processed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    const HASH: &str = "11111111111111111111111111111111";
+
+    /// A block at height 100, without chunks.
+    fn block() -> BlockView {
+        serde_json::from_value(json!({
+            "author": "test.near",
+            "header": {
+                "height": 100,
+                "prev_height": 99,
+                "epoch_id": HASH,
+                "next_epoch_id": HASH,
+                "hash": HASH,
+                "prev_hash": HASH,
+                "prev_state_root": HASH,
+                "chunk_receipts_root": HASH,
+                "chunk_headers_root": HASH,
+                "chunk_tx_root": HASH,
+                "outcome_root": HASH,
+                "chunks_included": 0,
+                "challenges_root": HASH,
+                "timestamp": 1_000,
+                "timestamp_nanosec": "1000",
+                "random_value": HASH,
+                "validator_proposals": [],
+                "chunk_mask": [],
+                "gas_price": "0",
+                "block_ordinal": null,
+                "rent_paid": "0",
+                "validator_reward": "0",
+                "total_supply": "0",
+                "challenges_result": [],
+                "last_final_block": HASH,
+                "last_ds_final_block": HASH,
+                "next_bp_hash": HASH,
+                "block_merkle_root": HASH,
+                "epoch_sync_data_hash": null,
+                "approvals": [],
+                "signature": format!("ed25519:{}", "1".repeat(64)),
+                "latest_protocol_version": 1
+            },
+            "chunks": []
+        }))
+        .expect("Invalid block")
+    }
+
+    /// An outcome of a receipt without actions that logged `logs`.
+    fn outcome(receiver_id: &str, logs: &[String]) -> IndexerExecutionOutcomeWithReceipt {
+        serde_json::from_value(json!({
+            "execution_outcome": {
+                "proof": [],
+                "block_hash": HASH,
+                "id": HASH,
+                "outcome": {
+                    "logs": logs,
+                    "receipt_ids": [],
+                    "gas_burnt": 0,
+                    "tokens_burnt": "0",
+                    "executor_id": receiver_id,
+                    "status": { "SuccessValue": "" }
+                }
+            },
+            "receipt": {
+                "predecessor_id": "alice.near",
+                "receiver_id": receiver_id,
+                "receipt_id": HASH,
+                "receipt": {
+                    "Action": {
+                        "signer_id": "alice.near",
+                        "signer_public_key": format!("ed25519:{HASH}"),
+                        "gas_price": "0",
+                        "output_data_receivers": [],
+                        "input_data_ids": [],
+                        "actions": []
+                    }
+                }
+            }
+        }))
+        .expect("Invalid outcome")
+    }
+
+    /// A transaction from `alice.near` to `receiver_id` without actions.
+    fn transaction(receiver_id: &str) -> near_indexer_primitives::IndexerTransactionWithOutcome {
+        serde_json::from_value(json!({
+            "transaction": {
+                "signer_id": "alice.near",
+                "public_key": format!("ed25519:{HASH}"),
+                "nonce": 0,
+                "receiver_id": receiver_id,
+                "actions": [],
+                "signature": format!("ed25519:{}", "1".repeat(64)),
+                "hash": HASH
+            },
+            "outcome": {
+                "execution_outcome": {
+                    "proof": [],
+                    "block_hash": HASH,
+                    "id": HASH,
+                    "outcome": {
+                        "logs": [],
+                        "receipt_ids": [HASH],
+                        "gas_burnt": 0,
+                        "tokens_burnt": "0",
+                        "executor_id": receiver_id,
+                        "status": { "SuccessValue": "" }
+                    }
+                },
+                "receipt": null
+            }
+        }))
+        .expect("Invalid transaction")
+    }
+
+    fn event_log(event: serde_json::Value) -> String {
+        format!("{EVENT_LOG_PREFIX}{event}")
+    }
+
+    fn token_logs() -> Vec<String> {
+        vec![
+            event_log(json!({
+                "standard": "nep141",
+                "version": "1.0.0",
+                "event": "ft_transfer",
+                "data": [
+                    { "old_owner_id": "alice.near", "new_owner_id": "bob.near", "amount": "100" },
+                    { "old_owner_id": "alice.near", "new_owner_id": "carol.near", "amount": "5" }
+                ]
+            })),
+            "Transfer 100 from alice.near to bob.near".to_string(),
+            format!("{EVENT_LOG_PREFIX}{{\"standard\": \"nep141\","),
+            event_log(json!({
+                "standard": "horizon",
+                "version": "1",
+                "event": "participant_funded",
+                "data": { "account_id": "dave.near", "amount": "50", "program": null }
+            })),
+        ]
+    }
+
+    #[test]
+    fn test_seconds_behind() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        // A block from 600000 seconds earlier, minus a few nanoseconds
+        let block_timestamp = 400_000 * 1_000_000_000 + 999;
+        assert_eq!(seconds_behind(block_timestamp, now), 599_999);
+        assert_eq!(seconds_behind(1_000_000 * 1_000_000_000, now), 0);
+        // Clocks can disagree, a block from the future isn't behind
+        assert_eq!(seconds_behind(2_000_000 * 1_000_000_000, now), 0);
+    }
+
+    #[test]
+    fn test_is_tx_receiver_watched() {
+        let watching_list = ["horizon.near".parse().unwrap()];
+        let suffixes = [normalize_suffix("horizon.near")];
+        let watched = |receiver_id| {
+            is_tx_receiver_watched(&transaction(receiver_id), &watching_list, &suffixes)
+        };
+
+        assert_eq!(normalize_suffix(" .horizon.near "), ".horizon.near");
+        assert!(watched("horizon.near"));
+        assert!(watched("app.horizon.near"));
+        // Suffixes only match on an account id boundary
+        assert!(!watched("apphorizon.near"));
+        assert!(!watched("other.near"));
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        // Block 102 was never written, 104 was skipped by the chain
+        let blocks = [
+            (100, Some(99)),
+            (101, Some(100)),
+            (103, Some(102)),
+            (105, Some(103)),
+        ];
+
+        assert_eq!(find_gaps(&blocks), vec![102..=102]);
+        assert!(find_gaps(&[]).is_empty());
+        assert!(find_gaps(&[(100, None), (101, None)]).is_empty());
+    }
+
+    #[test]
+    fn test_plan_resume() {
+        // Block 102 was never written, 104 was skipped by the chain
+        let blocks = [
+            (100, Some(99)),
+            (101, Some(100)),
+            (103, Some(102)),
+            (105, Some(103)),
+        ];
+
+        // Only the missing block is reprocessed, the processed ones are skipped
+        let resume = plan_resume(105, &blocks);
+        assert_eq!(resume.start_block_height, 102);
+        assert_eq!(resume.reprocessed(), vec![102]);
+        assert_eq!(resume.processed, HashSet::from([103, 105]));
+
+        // Without gaps the indexer resumes from the last visited block
+        let resume = plan_resume(101, &blocks[..2]);
+        assert_eq!(resume.start_block_height, 101);
+        assert!(resume.reprocessed().is_empty());
+        assert_eq!(resume.processed, HashSet::from([101]));
+    }
+
+    #[test]
+    fn test_extract_subject() {
+        let keys = ["account_id", "project_id", "id"].map(str::to_string);
+
+        let args = json!({ "account_id": "alice.near", "project_id": "project.near" });
+        assert_eq!(
+            extract_subject(&args, &keys),
+            Some("alice.near".to_string())
+        );
+
+        // Keys are tried in the order they're given, not the order of the args
+        let reversed = ["project_id", "account_id"].map(str::to_string);
+        assert_eq!(
+            extract_subject(&args, &reversed),
+            Some("project.near".to_string())
+        );
+
+        // Falls back to the next key when a key is missing or isn't a string
+        let args = json!({ "account_id": 42, "id": "request-1" });
+        assert_eq!(extract_subject(&args, &keys), Some("request-1".to_string()));
+
+        let args = json!({ "proposal": { "vendor_id": "vendor.near" } });
+        assert_eq!(
+            extract_subject(&args, &["proposal.vendor_id".to_string()]),
+            Some("vendor.near".to_string())
+        );
+
+        assert_eq!(extract_subject(&json!({ "amount": "1" }), &keys), None);
+        assert_eq!(extract_subject(&json!("alice.near"), &keys), None);
+    }
+
+    #[test]
+    fn test_decode_args() {
+        let engines = [
+            base64::engine::general_purpose::STANDARD,
+            base64::engine::general_purpose::URL_SAFE,
+        ];
+        let args = json!({ "account_id": "alice.near" });
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(args.to_string());
+        assert_eq!(
+            decode_args(encoded.as_bytes(), &engines),
+            (args.clone(), false)
+        );
+
+        // Encodes to a `-`, which only the URL safe alphabet accepts
+        let args = json!({ "memo": "??>>" });
+        let encoded = base64::engine::general_purpose::URL_SAFE.encode(args.to_string());
+        assert_eq!(
+            decode_args(encoded.as_bytes(), &engines),
+            (args.clone(), false)
+        );
+
+        assert_eq!(
+            decode_args(args.to_string().as_bytes(), &engines),
+            (args, false)
+        );
+
+        // Args that can't be decoded are kept as they are
+        assert_eq!(
+            decode_args(b"not json", &engines),
+            (json!("not json"), true)
+        );
+    }
+
+    #[test]
+    fn test_parse_token_events() {
+        let contract_id: near_indexer_primitives::types::AccountId = "token.near".parse().unwrap();
+        let tx_hash = HASH.parse().unwrap();
+
+        let events = parse_token_events(&token_logs(), &contract_id, tx_hash, &block());
+
+        // Each transfer of the batch is an event, other logs and malformed JSON are skipped
+        let parsed = events
+            .iter()
+            .map(|event| {
+                (
+                    event.standard.as_str(),
+                    event.event.as_str(),
+                    event.sender_id.as_deref(),
+                    event.receiver_id.as_deref(),
+                    event.amount.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parsed,
+            vec![
+                (
+                    "nep141",
+                    "ft_transfer",
+                    Some("alice.near"),
+                    Some("bob.near"),
+                    Some("100")
+                ),
+                (
+                    "nep141",
+                    "ft_transfer",
+                    Some("alice.near"),
+                    Some("carol.near"),
+                    Some("5")
+                ),
+                (
+                    "horizon",
+                    "participant_funded",
+                    None,
+                    Some("dave.near"),
+                    Some("50")
+                ),
+            ]
+        );
+        assert!(events.iter().all(|event| event.contract_id == contract_id
+            && event.hash == tx_hash
+            && event.timestamp == 1_000));
+    }
+
+    #[test]
+    fn test_process_outcome_token_accounts() {
+        let token: near_indexer_primitives::types::AccountId = "token.near".parse().unwrap();
+        let engines = [base64::engine::general_purpose::STANDARD];
+        let tx_hash = HASH.parse().unwrap();
+
+        let (_, events) = process_outcome(
+            outcome("token.near", &token_logs()),
+            tx_hash,
+            &engines,
+            &[],
+            std::slice::from_ref(&token),
+            &block(),
+        );
+        assert_eq!(events.len(), 3);
+
+        // Events logged by accounts that aren't watched token accounts are ignored
+        let (_, events) = process_outcome(
+            outcome("other.near", &token_logs()),
+            tx_hash,
+            &engines,
+            &[],
+            &[token],
+            &block(),
+        );
+        assert!(events.is_empty());
+    }
 }
@@ -1,27 +1,39 @@
-use std::collections::HashMap;
-use std::str::FromStr;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
 
 use indexer::collect_transactions;
 use indexer::filter_outcomes;
+use indexer::metrics::{self, Metrics};
+use indexer::plan_resume;
 use indexer::process_outcome;
+use indexer::seconds_behind;
+use indexer::sink::{PostgresSink, RedisSink, TransactionSink};
+use indexer::store::{TokenEvent, Transaction};
+use indexer::Resume;
 use itertools::Itertools;
-use near_lake_framework::near_indexer_primitives;
+use near_lake_framework::near_indexer_primitives::{self, views::BlockView};
 
-use configs::init_logging;
+use configs::{exit_invalid, init_logging, Opts, Sink};
 use tokio::sync::mpsc;
+use tracing::{info, info_span, warn, Instrument, Span};
 
 mod configs;
 
 /// Assuming we want to watch for transactions where a receiver account id is one of the provided in a list
 /// We pass the list of account ids (or contracts it is the same) via argument ``--accounts``
+/// and/or a file with one account id per line via ``--accounts-file``
 /// We want to catch all *successfull* transactions sent to one of the accounts from the list.
 /// In the demo we'll just look for them and log them but it might and probably should be extended based on your needs.
 
 #[tokio::main]
 async fn main() -> Result<(), tokio::io::Error> {
-    init_logging();
-
-    let accounts = std::env::var("ACCOUNTS").expect("ACCOUNTS is not set");
+    let opts = Opts::parse();
+    init_logging(opts.log_format);
+    let token_accounts = opts.token_accounts().unwrap_or_else(|e| exit_invalid(e));
+    let mut watching_list = opts.watching_list().unwrap_or_else(|e| exit_invalid(e));
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL is not set");
 
     let pool = sqlx::postgres::PgPoolOptions::new()
@@ -40,65 +52,564 @@ async fn main() -> Result<(), tokio::io::Error> {
         .await
         .expect("Failed to fetch max block height");
 
+    let processed_blocks = sqlx::query!(
+        r#"
+        SELECT height, prev_height
+        FROM processed_blocks
+        WHERE height >= $1
+        ORDER BY height
+        "#,
+        block_height.saturating_sub(opts.gap_scan_range as i64),
+    )
+    .fetch_all(&pool)
+    .await
+    .expect("Failed to fetch processed blocks")
+    .into_iter()
+    .map(|block| {
+        (
+            block.height as u64,
+            block.prev_height.map(|height| height as u64),
+        )
+    })
+    .collect_vec();
+
+    let Resume {
+        start_block_height,
+        gaps,
+        processed,
+    } = plan_resume(block_height as u64, &processed_blocks);
+    for gap in &gaps {
+        warn!(
+            from = gap.start(),
+            to = gap.end(),
+            "Detected gap in processed blocks, reprocessing it"
+        );
+    }
+    info!(start_block_height, "Starting the indexer");
+
     let config = near_lake_framework::LakeConfigBuilder::default()
-        .start_block_height(block_height as u64)
+        .start_block_height(start_block_height)
         .mainnet()
         .build()
         .expect("Failed to build LakeConfig");
 
     let (_, stream) = near_lake_framework::streamer(config);
 
-    let watching_list = accounts
-        .split(',')
-        .map(|elem| {
-            near_indexer_primitives::types::AccountId::from_str(elem).expect("AccountId is invalid")
-        })
-        .collect();
+    // Token events are only found in the outcomes of watched transactions
+    for account_id in &token_accounts {
+        if !watching_list.contains(account_id) {
+            watching_list.push(account_id.clone());
+        }
+    }
+    let watching_suffixes = opts.watching_suffixes();
+
+    tokio::spawn(prune_processed_blocks(pool.clone(), opts.gap_scan_range));
+
+    let metrics = Arc::new(Metrics::default());
+    tokio::spawn(metrics::serve(opts.metrics_addr, metrics.clone()));
+    tokio::spawn(metrics::poll_final_height(
+        opts.rpc_url.clone(),
+        Duration::from_secs(opts.final_block_poll_interval.max(1)),
+        metrics.clone(),
+    ));
 
-    listen_blocks(stream, watching_list, &pool).await;
+    let sink: Box<dyn TransactionSink> = match opts.sink {
+        Sink::Postgres => Box::new(PostgresSink::new(pool)),
+        Sink::Redis => Box::new(
+            RedisSink::new(
+                opts.redis_url
+                    .as_deref()
+                    .expect("REDIS_URL is required by the redis sink"),
+                opts.redis_stream,
+                pool,
+            )
+            .await
+            .expect("Failed to connect to Redis"),
+        ),
+    };
+
+    listen_blocks(
+        stream,
+        watching_list,
+        watching_suffixes,
+        token_accounts,
+        sink.as_ref(),
+        &metrics,
+        opts.lag_warn_threshold,
+        opts.arg_keys,
+        processed,
+        opts.write_queue_capacity,
+    )
+    .await;
 
     Ok(())
 }
 
+/// How often processed blocks that fell out of the gap scan range are deleted.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Deletes the processed blocks more than `gap_scan_range` blocks before the last
+/// visited one, they're never scanned for gaps again.
+async fn prune_processed_blocks(pool: sqlx::PgPool, gap_scan_range: u64) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM processed_blocks
+            WHERE height < (SELECT height FROM last_visited WHERE id = 1) - $1
+            "#,
+            gap_scan_range as i64,
+        )
+        .execute(&pool)
+        .await;
+
+        match result {
+            Ok(result) => info!(pruned = result.rows_affected(), "Pruned processed blocks"),
+            Err(e) => warn!("Failed to prune processed blocks: {e}"),
+        }
+    }
+}
+
+/// The transactions and token events extracted from a block, waiting to be written.
+struct ExtractedBlock {
+    block: BlockView,
+    txs: Vec<Transaction>,
+    events: Vec<TokenEvent>,
+    span: Span,
+}
+
 /// The main listener function the will be reading the stream of blocks `StreamerMessage`
-/// and perform necessary checks
+/// and perform necessary checks. Blocks in `processed` were handled by a previous run
+/// and are skipped.
+///
+/// Blocks are read and processed separately from writing them to the sink, with at
+/// most `write_queue_capacity` processed blocks waiting in between. Once the queue is
+/// full reading stops until the sink catches up, so a slow sink can't make the
+/// processed blocks pile up in memory.
+#[allow(clippy::too_many_arguments)]
 pub async fn listen_blocks(
+    stream: mpsc::Receiver<near_indexer_primitives::StreamerMessage>,
+    watching_list: Vec<near_indexer_primitives::types::AccountId>,
+    watching_suffixes: Vec<String>,
+    token_accounts: Vec<near_indexer_primitives::types::AccountId>,
+    sink: &dyn TransactionSink,
+    metrics: &Metrics,
+    lag_warn_threshold: u64,
+    arg_keys: Vec<String>,
+    processed: HashSet<u64>,
+    write_queue_capacity: usize,
+) {
+    let (sender, mut receiver) = mpsc::channel::<ExtractedBlock>(write_queue_capacity.max(1));
+
+    let reader = async move {
+        read_blocks(
+            stream,
+            sender,
+            watching_list,
+            watching_suffixes,
+            token_accounts,
+            metrics,
+            lag_warn_threshold,
+            arg_keys,
+            processed,
+        )
+        .await
+    };
+
+    let writer = async move {
+        while let Some(extracted) = receiver.recv().await {
+            sink.write(&extracted.block, &extracted.txs, &extracted.events)
+                .instrument(extracted.span)
+                .await
+                .expect("Failed to write transactions");
+        }
+    };
+
+    tokio::join!(reader, writer);
+}
+
+/// Reads the stream of blocks and extracts the watched transactions and token events,
+/// queueing them for the writer. Waits whenever the queue is full.
+#[allow(clippy::too_many_arguments)]
+async fn read_blocks(
     mut stream: mpsc::Receiver<near_indexer_primitives::StreamerMessage>,
+    sender: mpsc::Sender<ExtractedBlock>,
     watching_list: Vec<near_indexer_primitives::types::AccountId>,
-    pool: &sqlx::PgPool,
+    watching_suffixes: Vec<String>,
+    token_accounts: Vec<near_indexer_primitives::types::AccountId>,
+    metrics: &Metrics,
+    lag_warn_threshold: u64,
+    arg_keys: Vec<String>,
+    processed: HashSet<u64>,
 ) {
     // This will be a map of correspondence between transactions and receipts
     let mut tx_receipt_ids = HashMap::new();
     // This will be a list of receipt ids we're following
-    let engine = base64::engine::general_purpose::STANDARD;
+    let engines = [
+        base64::engine::general_purpose::STANDARD,
+        base64::engine::general_purpose::URL_SAFE,
+    ];
 
     // Boilerplate code to listen the stream
     while let Some(streamer_message) = stream.recv().await {
-        tx_receipt_ids.extend(collect_transactions(&streamer_message, &watching_list));
-        let txs = filter_outcomes(&streamer_message, &mut tx_receipt_ids)
-            .into_iter()
-            .flat_map(|(outcome, tx_hash)| {
-                process_outcome(outcome, tx_hash, &engine, &streamer_message.block)
+        let height = streamer_message.block.header.height;
+        let span = info_span!("block", component = "indexer", height);
+
+        let (txs, events) = span.in_scope(|| {
+            let lag = seconds_behind(streamer_message.block.header.timestamp, SystemTime::now());
+            metrics.record_block(height, lag);
+            if lag > lag_warn_threshold {
+                warn!(
+                    seconds_behind_tip = lag,
+                    blocks_behind_tip = metrics.blocks_behind_tip(),
+                    "Indexer is falling behind"
+                );
+            }
+
+            tx_receipt_ids.extend(collect_transactions(
+                &streamer_message,
+                &watching_list,
+                &watching_suffixes,
+            ));
+            let (txs, events): (Vec<_>, Vec<_>) =
+                filter_outcomes(&streamer_message, &mut tx_receipt_ids)
+                    .into_iter()
+                    .map(|(outcome, tx_hash)| {
+                        process_outcome(
+                            outcome,
+                            tx_hash,
+                            &engines,
+                            &arg_keys,
+                            &token_accounts,
+                            &streamer_message.block,
+                        )
+                    })
+                    .unzip();
+            (
+                txs.into_iter().flatten().collect_vec(),
+                events.into_iter().flatten().collect_vec(),
+            )
+        });
+
+        // Blocks handled by a previous run still go through the receipts tracking
+        // above so transactions spanning into the following blocks aren't lost
+        if processed.contains(&height) {
+            continue;
+        }
+
+        let extracted = ExtractedBlock {
+            block: streamer_message.block,
+            txs,
+            events,
+            span,
+        };
+        if sender.send(extracted).await.is_err() {
+            // The writer is gone, there is nothing left to write the blocks to
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    use base64::Engine as _;
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    const HASH: &str = "11111111111111111111111111111111";
+    const RECEIPT: &str = "11111111111111111111111111111112";
+    const OTHER_RECEIPT: &str = "11111111111111111111111111111113";
+
+    fn streamer_message(height: u64) -> near_indexer_primitives::StreamerMessage {
+        streamer_message_with_shards(height, vec![])
+    }
+
+    fn streamer_message_with_shards(
+        height: u64,
+        shards: Vec<serde_json::Value>,
+    ) -> near_indexer_primitives::StreamerMessage {
+        let signature = format!("ed25519:{}", "1".repeat(64));
+        serde_json::from_value(serde_json::json!({
+            "block": {
+                "author": "test.near",
+                "header": {
+                    "height": height,
+                    "prev_height": height - 1,
+                    "epoch_id": HASH,
+                    "next_epoch_id": HASH,
+                    "hash": HASH,
+                    "prev_hash": HASH,
+                    "prev_state_root": HASH,
+                    "chunk_receipts_root": HASH,
+                    "chunk_headers_root": HASH,
+                    "chunk_tx_root": HASH,
+                    "outcome_root": HASH,
+                    "chunks_included": 0,
+                    "challenges_root": HASH,
+                    "timestamp": 0,
+                    "timestamp_nanosec": "0",
+                    "random_value": HASH,
+                    "validator_proposals": [],
+                    "chunk_mask": [],
+                    "gas_price": "0",
+                    "block_ordinal": null,
+                    "rent_paid": "0",
+                    "validator_reward": "0",
+                    "total_supply": "0",
+                    "challenges_result": [],
+                    "last_final_block": HASH,
+                    "last_ds_final_block": HASH,
+                    "next_bp_hash": HASH,
+                    "block_merkle_root": HASH,
+                    "epoch_sync_data_hash": null,
+                    "approvals": [],
+                    "signature": signature,
+                    "latest_protocol_version": 1
+                },
+                "chunks": []
+            },
+            "shards": shards
+        }))
+        .expect("Invalid streamer message")
+    }
+
+    /// A shard whose chunk holds a transaction from `alice.near` calling `method_name`
+    /// on `receiver_id`, along with the successful outcome of its receipt `receipt_id`.
+    fn call_shard(
+        receipt_id: &str,
+        receiver_id: &str,
+        method_name: &str,
+        args: serde_json::Value,
+    ) -> serde_json::Value {
+        let signature = format!("ed25519:{}", "1".repeat(64));
+        let public_key = format!("ed25519:{HASH}");
+        let call = serde_json::json!({
+            "FunctionCall": {
+                "method_name": method_name,
+                "args": base64::engine::general_purpose::STANDARD.encode(args.to_string()),
+                "gas": 0,
+                "deposit": "0"
+            }
+        });
+        let outcome = |receipt_ids: Vec<&str>| {
+            serde_json::json!({
+                "proof": [],
+                "block_hash": HASH,
+                "id": HASH,
+                "outcome": {
+                    "logs": [],
+                    "receipt_ids": receipt_ids,
+                    "gas_burnt": 0,
+                    "tokens_burnt": "0",
+                    "executor_id": receiver_id,
+                    "status": { "SuccessValue": "" }
+                }
             })
-            .collect_vec();
+        };
 
-        for tx in txs {
-            tx.insert(pool)
-                .await
-                .expect("Failed to insert transaction");
+        serde_json::json!({
+            "shard_id": 0,
+            "chunk": {
+                "author": "test.near",
+                "header": {
+                    "chunk_hash": HASH,
+                    "prev_block_hash": HASH,
+                    "outcome_root": HASH,
+                    "prev_state_root": HASH,
+                    "encoded_merkle_root": HASH,
+                    "encoded_length": 0,
+                    "height_created": 0,
+                    "height_included": 0,
+                    "shard_id": 0,
+                    "gas_used": 0,
+                    "gas_limit": 0,
+                    "rent_paid": "0",
+                    "validator_reward": "0",
+                    "balance_burnt": "0",
+                    "outgoing_receipts_root": HASH,
+                    "tx_root": HASH,
+                    "validator_proposals": [],
+                    "signature": signature
+                },
+                "transactions": [{
+                    "transaction": {
+                        "signer_id": "alice.near",
+                        "public_key": public_key,
+                        "nonce": 0,
+                        "receiver_id": receiver_id,
+                        "actions": [call],
+                        "signature": signature,
+                        "hash": HASH
+                    },
+                    "outcome": {
+                        "execution_outcome": outcome(vec![receipt_id]),
+                        "receipt": null
+                    }
+                }],
+                "receipts": []
+            },
+            "receipt_execution_outcomes": [{
+                "execution_outcome": outcome(vec![]),
+                "receipt": {
+                    "predecessor_id": "alice.near",
+                    "receiver_id": receiver_id,
+                    "receipt_id": receipt_id,
+                    "receipt": {
+                        "Action": {
+                            "signer_id": "alice.near",
+                            "signer_public_key": public_key,
+                            "gas_price": "0",
+                            "output_data_receivers": [],
+                            "input_data_ids": [],
+                            "actions": [call]
+                        }
+                    }
+                }
+            }],
+            "state_changes": []
+        })
+    }
+
+    /// A sink keeping the transactions written to it in memory.
+    #[derive(Default)]
+    struct MemorySink {
+        txs: Mutex<Vec<serde_json::Value>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionSink for MemorySink {
+        async fn write(
+            &self,
+            _block: &BlockView,
+            txs: &[Transaction],
+            _events: &[TokenEvent],
+        ) -> anyhow::Result<()> {
+            for tx in txs {
+                self.txs.lock().unwrap().push(serde_json::to_value(tx)?);
+            }
+            Ok(())
         }
+    }
 
-        sqlx::query!(
-            r#"
-            INSERT INTO last_visited (id, height)
-            VALUES ($1, $2)
-            ON CONFLICT (id) DO UPDATE SET height = $2
-            "#,
-            1,
-            streamer_message.block.header.height as i64,
+    /// A sink that doesn't finish any write until it's released.
+    #[derive(Default)]
+    struct BlockingSink {
+        released: AtomicBool,
+        release: Notify,
+        written: Mutex<Vec<u64>>,
+    }
+
+    impl BlockingSink {
+        fn release(&self) {
+            self.released.store(true, Ordering::SeqCst);
+            self.release.notify_waiters();
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl TransactionSink for BlockingSink {
+        async fn write(
+            &self,
+            block: &BlockView,
+            _txs: &[Transaction],
+            _events: &[TokenEvent],
+        ) -> anyhow::Result<()> {
+            let released = self.release.notified();
+            if !self.released.load(Ordering::SeqCst) {
+                released.await;
+            }
+            self.written.lock().unwrap().push(block.header.height);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_sink_stops_reader() {
+        let (stream_sender, stream) = mpsc::channel(100);
+        for height in 1..=10 {
+            stream_sender.send(streamer_message(height)).await.unwrap();
+        }
+
+        let sink = BlockingSink::default();
+        let metrics = Metrics::default();
+
+        let listener = listen_blocks(
+            stream,
+            vec![],
+            vec![],
+            vec![],
+            &sink,
+            &metrics,
+            u64::MAX,
+            vec![],
+            HashSet::new(),
+            2,
+        );
+
+        let check = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            // One block is being written, two wait in the queue and the reader holds
+            // the fourth, the rest are left in the stream
+            assert_eq!(stream_sender.capacity(), 100 - 6);
+            assert_eq!(metrics.last_block_height(), 4);
+            assert!(sink.written.lock().unwrap().is_empty());
+
+            sink.release();
+            drop(stream_sender);
+        };
+
+        tokio::join!(listener, check);
+
+        assert_eq!(*sink.written.lock().unwrap(), (1..=10).collect_vec());
+    }
+
+    #[tokio::test]
+    async fn test_listen_blocks_writes_watched_calls() {
+        let (stream_sender, stream) = mpsc::channel(100);
+        let args = serde_json::json!({ "account_id": "bob.near" });
+        stream_sender
+            .send(streamer_message_with_shards(
+                1,
+                vec![
+                    call_shard(RECEIPT, "horizon.near", "add_project", args.clone()),
+                    call_shard(OTHER_RECEIPT, "other.near", "add_project", args),
+                ],
+            ))
+            .await
+            .unwrap();
+        drop(stream_sender);
+
+        let sink = MemorySink::default();
+        listen_blocks(
+            stream,
+            vec!["horizon.near".parse().unwrap()],
+            vec![],
+            vec![],
+            &sink,
+            &Metrics::default(),
+            u64::MAX,
+            vec!["account_id".to_string()],
+            HashSet::new(),
+            2,
         )
-        .execute(pool)
-        .await
-        .expect("Failed to insert block height");
+        .await;
+
+        // Only the call to the watched account is written
+        let txs = sink.txs.lock().unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0]["method_name"], "add_project");
+        assert_eq!(txs[0]["signer_id"], "alice.near");
+        assert_eq!(
+            txs[0]["args"],
+            serde_json::json!({ "account_id": "bob.near" })
+        );
+        assert_eq!(txs[0]["subject_id"], "bob.near");
     }
 }
@@ -0,0 +1,163 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use serde_json::json;
+use tracing::{info, warn};
+
+/// Progress of the indexer relative to the tip of the chain.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    last_block_height: AtomicU64,
+    seconds_behind_tip: AtomicU64,
+    final_block_height: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_block(&self, height: u64, seconds_behind_tip: u64) {
+        self.last_block_height.store(height, Ordering::Relaxed);
+        self.seconds_behind_tip
+            .store(seconds_behind_tip, Ordering::Relaxed);
+    }
+
+    /// Records the height of the latest final block of the chain.
+    pub fn record_final_block(&self, height: u64) {
+        self.final_block_height.store(height, Ordering::Relaxed);
+    }
+
+    pub fn last_block_height(&self) -> u64 {
+        self.last_block_height.load(Ordering::Relaxed)
+    }
+
+    pub fn seconds_behind_tip(&self) -> u64 {
+        self.seconds_behind_tip.load(Ordering::Relaxed)
+    }
+
+    /// How many blocks the last processed block is behind the latest final block, 0
+    /// until the final block height is known.
+    pub fn blocks_behind_tip(&self) -> u64 {
+        self.final_block_height
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.last_block_height())
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        [
+            (
+                "indexer_last_block_height",
+                "Height of the last processed block.",
+                self.last_block_height(),
+            ),
+            (
+                "indexer_seconds_behind_tip",
+                "How many seconds the last processed block is behind the current time.",
+                self.seconds_behind_tip(),
+            ),
+            (
+                "indexer_blocks_behind_tip",
+                "How many blocks the last processed block is behind the latest final block.",
+                self.blocks_behind_tip(),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, help, value)| {
+            format!("# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n")
+        })
+        .collect()
+    }
+}
+
+async fn get_metrics(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+}
+
+/// Serves the metrics on `GET /metrics`.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) {
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(metrics);
+
+    info!(%addr, "Serving metrics");
+    if let Err(e) = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .await
+    {
+        warn!("Metrics server stopped: {e}");
+    }
+}
+
+/// Fetches the height of the latest final block from the RPC.
+async fn fetch_final_height(client: &reqwest::Client, rpc_url: &str) -> anyhow::Result<u64> {
+    let response = client
+        .post(rpc_url)
+        .json(&json!({
+            "jsonrpc": "2.0",
+            "id": "indexer",
+            "method": "block",
+            "params": {
+                "finality": "final",
+            }
+        }))
+        .send()
+        .await?
+        .json::<serde_json::Value>()
+        .await?;
+
+    response["result"]["header"]["height"]
+        .as_u64()
+        .ok_or_else(|| anyhow::anyhow!("Unexpected RPC response: {response}"))
+}
+
+/// Keeps the latest final block height up to date, polling the RPC every `interval`.
+pub async fn poll_final_height(rpc_url: String, interval: Duration, metrics: Arc<Metrics>) {
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(interval);
+
+    loop {
+        interval.tick().await;
+        match fetch_final_height(&client, &rpc_url).await {
+            Ok(height) => metrics.record_final_block(height),
+            Err(e) => warn!("Failed to fetch the final block height: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_behind_tip() {
+        let metrics = Metrics::default();
+        metrics.record_block(100, 5);
+
+        // Unknown until the final height is fetched
+        assert_eq!(metrics.blocks_behind_tip(), 0);
+
+        metrics.record_final_block(130);
+        assert_eq!(metrics.blocks_behind_tip(), 30);
+
+        // The final height can be stale
+        metrics.record_block(140, 0);
+        assert_eq!(metrics.blocks_behind_tip(), 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_block(100, 42);
+        metrics.record_final_block(110);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE indexer_last_block_height gauge\n"));
+        assert!(rendered.contains("\nindexer_last_block_height 100\n"));
+        assert!(rendered.contains("\nindexer_seconds_behind_tip 42\n"));
+        assert!(rendered.contains("\nindexer_blocks_behind_tip 10\n"));
+    }
+}
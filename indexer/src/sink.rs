@@ -0,0 +1,158 @@
+use near_lake_framework::near_indexer_primitives::views::BlockView;
+use redis::aio::MultiplexedConnection;
+
+use crate::store::{TokenEvent, Transaction};
+
+/// A destination for the transactions and token events found in each block.
+#[async_trait::async_trait]
+pub trait TransactionSink: Send + Sync {
+    /// Writes the transactions and token events found in `block` and marks the block
+    /// as processed.
+    async fn write(
+        &self,
+        block: &BlockView,
+        txs: &[Transaction],
+        events: &[TokenEvent],
+    ) -> anyhow::Result<()>;
+}
+
+/// Records the block as processed so restarts resume after it and gaps can be found.
+async fn record_block(
+    connection: &mut sqlx::PgConnection,
+    block: &BlockView,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO processed_blocks (height, prev_height)
+        VALUES ($1, $2)
+        ON CONFLICT (height) DO NOTHING
+        "#,
+        block.header.height as i64,
+        block.header.prev_height.map(|height| height as i64),
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    // The timestamp follows the height so it's always the one of the highest block
+    sqlx::query!(
+        r#"
+        INSERT INTO last_visited (id, height, timestamp)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET
+          height = GREATEST(last_visited.height, $2),
+          timestamp = CASE
+            WHEN $2 >= last_visited.height THEN $3
+            ELSE last_visited.timestamp
+          END
+        "#,
+        1,
+        block.header.height as i64,
+        block.header.timestamp as i64,
+    )
+    .execute(&mut *connection)
+    .await?;
+
+    Ok(())
+}
+
+/// Stores transactions in the `transactions` table and token events in the
+/// `token_events` table.
+pub struct PostgresSink {
+    pool: sqlx::PgPool,
+}
+
+impl PostgresSink {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionSink for PostgresSink {
+    async fn write(
+        &self,
+        block: &BlockView,
+        txs: &[Transaction],
+        events: &[TokenEvent],
+    ) -> anyhow::Result<()> {
+        // The transactions and the processed block are committed together so a
+        // crash can't leave a block half processed
+        let mut db_tx = self.pool.begin().await?;
+
+        for tx in txs {
+            tx.insert(&mut db_tx).await?;
+        }
+
+        for event in events {
+            event.insert(&mut db_tx).await?;
+            event.record_registrations(&mut db_tx).await?;
+        }
+
+        record_block(&mut db_tx, block).await?;
+
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Publishes transactions and token events as JSON entries (under the `tx` and
+/// `event` fields respectively) to a Redis stream.
+/// Progress is still kept in Postgres, a block is only marked as processed after
+/// its transactions are published so consumers get each one at least once.
+pub struct RedisSink {
+    connection: MultiplexedConnection,
+    stream: String,
+    pool: sqlx::PgPool,
+}
+
+impl RedisSink {
+    pub async fn new(url: &str, stream: String, pool: sqlx::PgPool) -> anyhow::Result<Self> {
+        let connection = redis::Client::open(url)?
+            .get_multiplexed_tokio_connection()
+            .await?;
+
+        Ok(Self {
+            connection,
+            stream,
+            pool,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TransactionSink for RedisSink {
+    async fn write(
+        &self,
+        block: &BlockView,
+        txs: &[Transaction],
+        events: &[TokenEvent],
+    ) -> anyhow::Result<()> {
+        if !txs.is_empty() || !events.is_empty() {
+            let mut pipe = redis::pipe();
+            pipe.atomic();
+            for tx in txs {
+                pipe.cmd("XADD")
+                    .arg(&self.stream)
+                    .arg("*")
+                    .arg("tx")
+                    .arg(serde_json::to_string(tx)?)
+                    .ignore();
+            }
+            for event in events {
+                pipe.cmd("XADD")
+                    .arg(&self.stream)
+                    .arg("*")
+                    .arg("event")
+                    .arg(serde_json::to_string(event)?)
+                    .ignore();
+            }
+            pipe.query_async::<_, ()>(&mut self.connection.clone())
+                .await?;
+        }
+
+        record_block(&mut *self.pool.acquire().await?, block).await?;
+
+        Ok(())
+    }
+}
@@ -7,17 +7,149 @@ pub struct Transaction {
     pub signer_id: AccountId,
     pub method_name: String,
     pub args: serde_json::Value,
+    pub args_decode_failed: bool,
+    pub subject_id: Option<String>,
     pub log: String,
     pub block_hash: CryptoHash,
     pub timestamp: u64,
 }
 
+/// An item of a NEP-297 event logged by a watched token contract.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenEvent {
+    pub hash: CryptoHash,
+    pub contract_id: AccountId,
+    pub standard: String,
+    pub event: String,
+    /// The account the tokens left, `None` for mints.
+    pub sender_id: Option<String>,
+    /// The account the tokens went to, `None` for burns.
+    pub receiver_id: Option<String>,
+    pub amount: Option<String>,
+    pub data: serde_json::Value,
+    pub block_hash: CryptoHash,
+    pub timestamp: u64,
+}
+
+impl TokenEvent {
+    /// Creates the event, picking the accounts and amount out of `data` based on the
+    /// field names of the NEP-141 events. Horizon events name the receiving account
+    /// `account_id`.
+    pub fn new(
+        hash: CryptoHash,
+        contract_id: AccountId,
+        standard: String,
+        event: String,
+        data: serde_json::Value,
+        block_hash: CryptoHash,
+        timestamp: u64,
+    ) -> Self {
+        let field = |key: &str| {
+            data.get(key)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        let (sender_id, receiver_id) = match event.as_str() {
+            "ft_mint" => (None, field("owner_id")),
+            "ft_burn" => (field("owner_id"), None),
+            _ => (
+                field("old_owner_id"),
+                field("new_owner_id").or_else(|| field("account_id")),
+            ),
+        };
+        let amount = field("amount").filter(|amount| amount.parse::<u128>().is_ok());
+
+        Self {
+            hash,
+            contract_id,
+            standard,
+            event,
+            sender_id,
+            receiver_id,
+            amount,
+            data,
+            block_hash,
+            timestamp,
+        }
+    }
+
+    pub async fn insert<'c, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::PgExecutor<'c>,
+    {
+        sqlx::query!(
+            r#"
+            INSERT INTO token_events (hash, contract_id, standard, event, sender_id, receiver_id, amount, data, block_hash, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7::text::numeric, $8, $9, $10)
+            "#,
+            self.hash.to_string(),
+            self.contract_id.to_string(),
+            self.standard,
+            self.event,
+            self.sender_id,
+            self.receiver_id,
+            self.amount,
+            self.data,
+            self.block_hash.to_string(),
+            self.timestamp as i64,
+        )
+        .execute(executor)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The accounts the event shows are on the token's allowlist. Horizon tokens only
+    /// transfer between registered accounts, and funding or registering a holder or a
+    /// provider adds them to the allowlist.
+    pub fn registered_accounts(&self) -> Vec<&str> {
+        match self.event.as_str() {
+            "ft_transfer" => [&self.sender_id, &self.receiver_id]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect(),
+            "participant_funded" | "holder_registered" | "provider_registered" => {
+                self.receiver_id.iter().map(String::as_str).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Adds the [`TokenEvent::registered_accounts`] to the `credits_registrations`
+    /// snapshot, keeping the time each account was first seen.
+    pub async fn record_registrations(
+        &self,
+        connection: &mut sqlx::PgConnection,
+    ) -> Result<(), sqlx::Error> {
+        for account_id in self.registered_accounts() {
+            sqlx::query!(
+                r#"
+                INSERT INTO credits_registrations (account_id, contract_id, registered_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (account_id, contract_id) DO NOTHING
+                "#,
+                account_id,
+                self.contract_id.to_string(),
+                self.timestamp as i64,
+            )
+            .execute(&mut *connection)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
 impl Transaction {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         hash: CryptoHash,
         signer_id: AccountId,
         method_name: String,
         args: serde_json::Value,
+        args_decode_failed: bool,
+        subject_id: Option<String>,
         log: String,
         block_hash: CryptoHash,
         timestamp: u64,
@@ -27,29 +159,36 @@ impl Transaction {
             signer_id,
             method_name,
             args,
+            args_decode_failed,
+            subject_id,
             log,
             block_hash,
             timestamp,
         }
     }
 
-    pub async fn insert(&self, pool: &sqlx::PgPool) -> Result<(), sqlx::Error> {
+    pub async fn insert<'c, E>(&self, executor: E) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::PgExecutor<'c>,
+    {
         eprintln!("Inserting transaction: {self:#?}");
 
         sqlx::query!(
             r#"
-            INSERT INTO transactions (hash, signer_id, method_name, args, log, block_hash, timestamp)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO transactions (hash, signer_id, method_name, args, args_decode_failed, subject_id, log, block_hash, timestamp)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             "#,
             self.hash.to_string(),
             self.signer_id.to_string(),
             self.method_name,
             self.args,
+            self.args_decode_failed,
+            self.subject_id,
             self.log,
             self.block_hash.to_string(),
             self.timestamp as i64,
         )
-        .execute(pool)
+        .execute(executor)
         .await?;
 
         eprintln!("Transaction inserted");
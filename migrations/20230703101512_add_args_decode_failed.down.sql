@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE transactions
+DROP COLUMN args_decode_failed;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE transactions
+ADD COLUMN args_decode_failed boolean NOT NULL DEFAULT false;
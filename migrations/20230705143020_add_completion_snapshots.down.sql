@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TRIGGER IF EXISTS projects_completion_snapshot ON projects;
+
+DROP FUNCTION IF EXISTS snapshot_project_completion;
+
+DROP TABLE IF EXISTS completion_snapshots;
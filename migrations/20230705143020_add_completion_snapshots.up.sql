@@ -0,0 +1,28 @@
+-- Add up migration script here
+CREATE TABLE completion_snapshots (
+  id SERIAL PRIMARY KEY,
+  project_id varchar(64) NOT NULL,
+  completion double precision NOT NULL,
+  timestamp bigint NOT NULL
+);
+
+CREATE INDEX completion_snapshots_project_id_idx ON completion_snapshots (project_id, timestamp);
+
+-- Record the completion every time a project is added or its completion changes
+CREATE FUNCTION snapshot_project_completion() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'INSERT' OR NEW.completion IS DISTINCT FROM OLD.completion THEN
+    INSERT INTO completion_snapshots (project_id, completion, timestamp)
+    VALUES (NEW.id, NEW.completion, (EXTRACT(EPOCH FROM now()) * 1000000000)::bigint);
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER projects_completion_snapshot
+AFTER INSERT OR UPDATE ON projects
+FOR EACH ROW EXECUTE FUNCTION snapshot_project_completion();
+
+-- Start the history off with the current completion of every project
+INSERT INTO completion_snapshots (project_id, completion, timestamp)
+SELECT id, completion, (EXTRACT(EPOCH FROM now()) * 1000000000)::bigint FROM projects;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS transactions_subject_id_idx;
+
+ALTER TABLE transactions
+DROP COLUMN subject_id;
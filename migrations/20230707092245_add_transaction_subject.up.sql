@@ -0,0 +1,8 @@
+-- Add up migration script here
+ALTER TABLE transactions
+ADD COLUMN subject_id varchar(64);
+
+UPDATE transactions
+SET subject_id = COALESCE(args ->> 'account_id', args ->> 'project_id');
+
+CREATE INDEX transactions_subject_id_idx ON transactions (subject_id);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE processed_blocks (
+  height bigint PRIMARY KEY,
+  prev_height bigint
+);
+
+INSERT INTO processed_blocks (height, prev_height)
+SELECT height, NULL FROM last_visited WHERE id = 1;
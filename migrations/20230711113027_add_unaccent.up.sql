@@ -0,0 +1,2 @@
+-- Add up migration script here
+CREATE EXTENSION IF NOT EXISTS unaccent;
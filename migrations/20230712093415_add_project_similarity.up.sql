@@ -0,0 +1,87 @@
+-- Add up migration script here
+CREATE MATERIALIZED VIEW project_similarity AS
+WITH sized AS (
+  SELECT
+    projects.*,
+    (
+      CASE jsonb_typeof(projects.team)
+        WHEN 'object' THEN (
+          SELECT
+            COUNT(*)
+          FROM
+            jsonb_object_keys(projects.team)
+        )
+        ELSE 0
+      END
+    ) + COALESCE(array_length(projects.founders, 1), 0) AS team_size
+  FROM
+    projects
+),
+ranked AS (
+  SELECT
+    target.id AS project_id,
+    candidate.id AS similar_id,
+    ROW_NUMBER() OVER (
+      PARTITION BY target.id
+      ORDER BY
+        (
+          SELECT
+            COUNT(*)
+          FROM
+            unnest(candidate.product_type) AS v
+          WHERE
+            v = ANY (target.product_type)
+        ) + (
+          SELECT
+            COUNT(*)
+          FROM
+            jsonb_object_keys(candidate.vertical) AS v
+          WHERE
+            target.vertical ? v
+        ) + (
+          candidate.stage ILIKE target.stage
+        ) :: int + (
+          candidate.dev ILIKE target.dev
+        ) :: int + (
+          candidate.distribution ILIKE target.distribution
+        ) :: int + 1.0 / (
+          1 + ABS(candidate.team_size - target.team_size)
+        ) DESC,
+        candidate.id ASC
+    ) AS rank
+  FROM
+    sized AS target
+    JOIN sized AS candidate ON candidate.id != target.id
+  WHERE
+    (
+      SELECT
+        COUNT(*)
+      FROM
+        jsonb_object_keys(candidate.vertical) AS v
+      WHERE
+        target.vertical ? v
+    ) > 0
+    OR (
+      SELECT
+        COUNT(*)
+      FROM
+        unnest(candidate.product_type) AS v
+      WHERE
+        v = ANY (target.product_type)
+    ) > 0
+    OR candidate.stage ILIKE target.stage
+    OR candidate.dev ILIKE target.dev
+    OR candidate.distribution ILIKE target.distribution
+)
+SELECT
+  project_id,
+  similar_id,
+  rank
+FROM
+  ranked
+WHERE
+  rank <= 100;
+
+-- Needed to refresh the view concurrently
+CREATE UNIQUE INDEX project_similarity_pair_idx ON project_similarity (project_id, similar_id);
+CREATE INDEX project_similarity_rank_idx ON project_similarity (project_id, rank);
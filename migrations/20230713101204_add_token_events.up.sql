@@ -0,0 +1,19 @@
+-- Add up migration script here
+CREATE TABLE token_events (
+  id SERIAL PRIMARY KEY,
+  hash char(44) NOT NULL,
+  contract_id varchar(64) NOT NULL,
+  standard varchar(64) NOT NULL,
+  event varchar(64) NOT NULL,
+  sender_id varchar(64),
+  receiver_id varchar(64),
+  amount numeric(40, 0),
+  data jsonb NOT NULL,
+  block_hash char(44) NOT NULL,
+  timestamp bigint NOT NULL
+);
+
+CREATE INDEX token_events_hash_idx ON token_events (hash);
+CREATE INDEX token_events_sender_id_idx ON token_events (sender_id);
+CREATE INDEX token_events_receiver_id_idx ON token_events (receiver_id);
+CREATE INDEX token_events_timestamp_idx ON token_events (timestamp);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE last_visited
+DROP COLUMN timestamp;
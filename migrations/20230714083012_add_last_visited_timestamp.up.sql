@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE last_visited
+ADD COLUMN timestamp bigint;
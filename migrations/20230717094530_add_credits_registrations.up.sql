@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE credits_registrations (
+  account_id varchar(64) NOT NULL,
+  contract_id varchar(64) NOT NULL,
+  registered_at bigint NOT NULL,
+  PRIMARY KEY (account_id, contract_id)
+);
+
+INSERT INTO credits_registrations (account_id, contract_id, registered_at)
+SELECT registrations.account_id, registrations.contract_id, MIN(registrations.timestamp)
+FROM (
+  SELECT receiver_id AS account_id, contract_id, timestamp
+  FROM token_events
+  WHERE event IN ('ft_transfer', 'participant_funded', 'holder_registered')
+  UNION ALL
+  SELECT sender_id AS account_id, contract_id, timestamp
+  FROM token_events
+  WHERE event = 'ft_transfer'
+) AS registrations
+WHERE registrations.account_id IS NOT NULL
+GROUP BY registrations.account_id, registrations.contract_id;
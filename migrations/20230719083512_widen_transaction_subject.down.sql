@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE transactions
+ALTER COLUMN subject_id TYPE varchar(64) USING LEFT(subject_id, 64);
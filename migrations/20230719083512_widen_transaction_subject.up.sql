@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Subjects come from arbitrary call arguments, which aren't bound to account id lengths
+ALTER TABLE transactions
+ALTER COLUMN subject_id TYPE text;
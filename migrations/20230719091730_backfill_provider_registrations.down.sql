@@ -0,0 +1,3 @@
+-- Add down migration script here
+-- The backfilled providers are indistinguishable from the other registrations and
+-- stay registered, the indexer records them either way
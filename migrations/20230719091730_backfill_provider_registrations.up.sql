@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- Providers are on the allowlist too, but were left out of the first backfill
+INSERT INTO credits_registrations (account_id, contract_id, registered_at)
+SELECT receiver_id, contract_id, MIN(timestamp)
+FROM token_events
+WHERE event = 'provider_registered' AND receiver_id IS NOT NULL
+GROUP BY receiver_id, contract_id
+ON CONFLICT (account_id, contract_id) DO UPDATE SET
+  registered_at = LEAST(credits_registrations.registered_at, EXCLUDED.registered_at);
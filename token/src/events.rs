@@ -0,0 +1,19 @@
+use near_sdk::json_types::U128;
+use near_sdk::AccountId;
+use near_sdk_contract_tools::event;
+
+#[event(standard = "horizon", version = "1", serde = "near_sdk::serde")]
+pub enum Events {
+    ParticipantFunded {
+        account_id: AccountId,
+        amount: U128,
+        program: Option<String>,
+    },
+    HolderRegistered {
+        account_id: AccountId,
+        cohort: Option<String>,
+    },
+    ProviderRegistered {
+        account_id: AccountId,
+    },
+}
@@ -1,18 +1,41 @@
+use std::collections::HashSet;
+
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::U128;
-use near_sdk::store::LookupSet;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::store::{LookupMap, LookupSet, Vector};
 use near_sdk::{assert_one_yocto, env, require};
-use near_sdk::{near_bindgen, AccountId};
+use near_sdk::{near_bindgen, AccountId, PanicOnDefault, Promise};
 use near_sdk_contract_tools::owner::OwnerExternal;
 use near_sdk_contract_tools::standard::nep141::{
     Nep141, Nep141Controller, Nep141Hook, Nep141Transfer,
 };
-use near_sdk_contract_tools::{owner::Owner, FungibleToken, Owner};
+use near_sdk_contract_tools::standard::nep148::{FungibleTokenMetadata, Nep148, FT_METADATA_SPEC};
+use near_sdk_contract_tools::standard::nep297::Event;
+use near_sdk_contract_tools::{owner::Owner, Nep141, Owner};
+
+use crate::events::Events;
 
-/// The versioned whitelist item.
+pub mod events;
+
+/// The versioned whitelist item. `V0` items were stored as set members, `V1` items
+/// are stored by account id and carry the cohort the account belongs to.
 #[derive(BorshDeserialize, BorshSerialize)]
 enum VersionedAllowList {
     V0(AccountId),
+    V1 {
+        account_id: AccountId,
+        cohort: Option<String>,
+    },
+}
+
+impl VersionedAllowList {
+    fn cohort(&self) -> Option<&str> {
+        match self {
+            VersionedAllowList::V0(_) => None,
+            VersionedAllowList::V1 { cohort, .. } => cohort.as_deref(),
+        }
+    }
 }
 
 impl From<AccountId> for VersionedAllowList {
@@ -25,40 +48,123 @@ impl From<VersionedAllowList> for AccountId {
     fn from(value: VersionedAllowList) -> Self {
         match value {
             VersionedAllowList::V0(account_id) => account_id,
+            VersionedAllowList::V1 { account_id, .. } => account_id,
+        }
+    }
+}
+
+/// The parts of the token metadata the owner can change with
+/// [`Contract::set_metadata`]. Decimals never change, see [`DECIMALS`].
+#[derive(BorshDeserialize, BorshSerialize)]
+struct MutableMetadata {
+    name: String,
+    symbol: String,
+    reference: Option<String>,
+}
+
+impl Default for MutableMetadata {
+    fn default() -> Self {
+        Self {
+            name: "NEAR Horizon".to_string(),
+            symbol: "NHZN".to_string(),
+            reference: None,
         }
     }
 }
 
+/// The token decimals, fixed since changing them would change every balance.
+const DECIMALS: u8 = 4;
+
+/// The contract state before cohorts, see [`Contract::migrate`].
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV0 {
+    allowlist: LookupSet<VersionedAllowList>,
+    fund_amount: u128,
+}
+
 /// The fungible token contract struct.
-#[derive(Owner, FungibleToken)]
-#[fungible_token(name = "NEAR Horizon", symbol = "NHZN", decimals = 4)]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault, Owner, Nep141)]
 #[near_bindgen]
 pub struct Contract {
-    allowlist: LookupSet<VersionedAllowList>,
+    /// Allowlist items registered before cohorts, see [`Contract::migrate_allowlist`].
+    legacy_allowlist: LookupSet<VersionedAllowList>,
     fund_amount: u128,
+    total_awarded: LookupMap<AccountId, u128>,
+    idempotency_keys: LookupSet<String>,
+    allowlist: LookupMap<AccountId, VersionedAllowList>,
+    /// The members of each cohort, see [`Contract::holders_in_cohort`].
+    cohorts: LookupMap<String, Vector<AccountId>>,
+    /// The index of each cohort member in its cohort's members.
+    cohort_positions: LookupMap<AccountId, u32>,
+    metadata: MutableMetadata,
+    /// Service providers, the accounts participants can spend credits at while
+    /// spending is restricted, see [`Contract::set_spending_restricted`].
+    providers: LookupSet<AccountId>,
+    spending_restricted: bool,
+    /// The UTC day (days since the epoch) each account was last funded on and the
+    /// amount it was funded with that day.
+    daily_funding: LookupMap<AccountId, (u64, u128)>,
+    max_daily_per_account: Option<u128>,
+}
+
+/// The contract configuration returned by [`Contract::get_config`]. The contract has
+/// no pause switch, managers or cap on fund amounts, and the allowlist is a map that
+/// isn't counted, so none of those are part of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractConfig {
+    pub owner: Option<AccountId>,
+    pub fund_amount: U128,
+    pub symbol: String,
+    pub decimals: u8,
+    pub spending_restricted: bool,
+    pub max_daily_per_account: Option<U128>,
 }
 
+/// Nanoseconds in a day, block timestamps are in nanoseconds.
+const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
 /// A constant representing one NEAR Horizon token (10^4 miliNHZN).
 const ONE_NHZN: u128 = 1_000;
 
+/// The most cohort members [`Contract::fund_cohort`] funds in one call, so a call
+/// stays well within the gas limit.
+const MAX_COHORT_BATCH: u32 = 50;
+
+/// The storage prefix of the cohort's members.
+fn cohort_members_prefix(cohort: &str) -> Vec<u8> {
+    [b"n".as_slice(), &env::sha256(cohort.as_bytes())].concat()
+}
+
 #[near_bindgen]
 impl Contract {
     #[init]
     pub fn new(owner_id: AccountId, total_supply: U128, fund_amount: Option<U128>) -> Self {
-        let mut contract = Self {
+        let mut contract = Self::with_legacy_state(ContractV0 {
             allowlist: LookupSet::new(b"a"),
             fund_amount: fund_amount
                 .map(|fund_amount| fund_amount.into())
                 .unwrap_or(50_000 * ONE_NHZN),
-        };
+        });
 
         Owner::init(&mut contract, &owner_id);
-        contract.allowlist.insert(owner_id.clone().into());
+        contract.register(owner_id.clone(), None);
         contract.deposit_unchecked(&owner_id, total_supply.into());
 
         contract
     }
 
+    /// Migrates the state deployed before cohorts. Owner and balances are kept in
+    /// their own storage and aren't touched, legacy allowlist items are moved over
+    /// with [`Contract::migrate_allowlist`].
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state: Option<ContractV0> = env::state_read();
+        require!(state.is_some(), "ERR_NO_STATE");
+        Self::with_legacy_state(state.unwrap())
+    }
+
     #[payable]
     pub fn add_deposit(&mut self, deposit: U128) {
         self.assert_owner();
@@ -66,11 +172,98 @@ impl Contract {
         self.deposit_unchecked(&self.own_get_owner().unwrap(), deposit.into());
     }
 
+    /// Registers the account on the allowlist, keeping its cohort if it has one.
     #[payable]
     pub fn register_holder(&mut self, account_id: AccountId) {
         self.assert_owner();
         assert_one_yocto();
-        self.allowlist.insert(account_id.into());
+        self.register(account_id.clone(), None);
+        Events::HolderRegistered {
+            cohort: self.cohort_of(account_id.clone()),
+            account_id,
+        }
+        .emit();
+    }
+
+    /// Registers the account on the allowlist as a member of the cohort, moving it
+    /// out of its previous cohort.
+    #[payable]
+    pub fn register_holder_in_cohort(&mut self, account_id: AccountId, cohort: String) {
+        self.assert_owner();
+        assert_one_yocto();
+        require!(!cohort.trim().is_empty(), "ERR_EMPTY_COHORT");
+        self.register(account_id.clone(), Some(cohort.clone()));
+        Events::HolderRegistered {
+            account_id,
+            cohort: Some(cohort),
+        }
+        .emit();
+    }
+
+    /// Registers the account on the allowlist as a service provider, so participants
+    /// can spend their credits with it while spending is restricted.
+    #[payable]
+    pub fn register_provider(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.register(account_id.clone(), None);
+        self.providers.insert(account_id.clone());
+        Events::ProviderRegistered { account_id }.emit();
+    }
+
+    /// Limits transfers from anyone but the owner to providers and the owner, so
+    /// credits can only be spent at approved service providers and settled back with
+    /// the owner. Owner funding is never limited.
+    #[payable]
+    pub fn set_spending_restricted(&mut self, restricted: bool) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.spending_restricted = restricted;
+    }
+
+    /// Caps how much a single account can be funded with per UTC day, across all of
+    /// the fund methods. `None` removes the cap.
+    #[payable]
+    pub fn set_max_daily_per_account(&mut self, max_daily_per_account: Option<U128>) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.max_daily_per_account = max_daily_per_account.map(u128::from);
+    }
+
+    /// Changes the token name, symbol or reference, leaving out the ones that are
+    /// `None`. Decimals can't be changed.
+    #[payable]
+    pub fn set_metadata(
+        &mut self,
+        name: Option<String>,
+        symbol: Option<String>,
+        reference: Option<String>,
+    ) {
+        self.assert_owner();
+        assert_one_yocto();
+        if let Some(name) = name {
+            self.metadata.name = name;
+        }
+        if let Some(symbol) = symbol {
+            require!(!symbol.trim().is_empty(), "ERR_EMPTY_SYMBOL");
+            self.metadata.symbol = symbol;
+        }
+        if reference.is_some() {
+            self.metadata.reference = reference;
+        }
+    }
+
+    /// Moves allowlist items registered before cohorts over to the current allowlist.
+    /// Accounts that aren't legacy items are ignored.
+    #[payable]
+    pub fn migrate_allowlist(&mut self, account_ids: Vec<AccountId>) {
+        self.assert_owner();
+        assert_one_yocto();
+        for account_id in account_ids {
+            if self.legacy_allowlist.contains(&account_id.clone().into()) {
+                self.register(account_id, None);
+            }
+        }
     }
 
     #[payable]
@@ -78,7 +271,7 @@ impl Contract {
         assert_one_yocto();
         let claimer = env::predecessor_account_id();
 
-        self.transfer(
+        self.privileged_transfer(
             claimer.clone(),
             self.own_get_owner().unwrap(),
             self.ft_balance_of(claimer).into(),
@@ -86,39 +279,459 @@ impl Contract {
         );
     }
 
+    /// Funds a program participant. A call with an `idempotency_key` that was already
+    /// used by any of the fund methods does nothing, so retries can't fund twice.
+    #[payable]
+    pub fn fund_program_participant(
+        &mut self,
+        account_id: AccountId,
+        memo: Option<String>,
+        idempotency_key: Option<String>,
+    ) {
+        self.assert_owner();
+        assert_one_yocto();
+        if !self.use_idempotency_key(idempotency_key) {
+            return;
+        }
+        self.require_owner_balance(self.fund_amount);
+        self.fund_participant(account_id, self.fund_amount, memo);
+    }
+
+    /// Funds all of the accounts or none of them. The whole list is validated before
+    /// any account is registered or funded. Idempotency keys are shared with
+    /// [`Contract::fund_program_participant`].
+    #[payable]
+    pub fn fund_program_participants(
+        &mut self,
+        account_ids: Vec<AccountId>,
+        memo: Option<String>,
+        idempotency_key: Option<String>,
+    ) {
+        self.assert_owner();
+        assert_one_yocto();
+        if !self.use_idempotency_key(idempotency_key) {
+            return;
+        }
+
+        if let Some((account_id, error)) = self
+            .validate_funding(account_ids.clone(), self.fund_amount.into())
+            .into_iter()
+            .next()
+        {
+            require!(false, format!("{error}: {account_id}"));
+        }
+
+        for account_id in account_ids {
+            self.fund_participant(account_id, self.fund_amount, memo.clone());
+        }
+    }
+
+    /// Funds the cohort members `from..from + limit` (see
+    /// [`Contract::holders_in_cohort`]) with `amount`, all of them or none. At most
+    /// [`MAX_COHORT_BATCH`] members are funded per call, larger cohorts are funded in
+    /// ranges. The cohort label is used as the memo. Idempotency keys are shared with
+    /// [`Contract::fund_program_participant`].
+    #[payable]
+    pub fn fund_cohort(
+        &mut self,
+        cohort: String,
+        amount: U128,
+        from: Option<u32>,
+        limit: Option<u32>,
+        idempotency_key: Option<String>,
+    ) {
+        self.assert_owner();
+        assert_one_yocto();
+        let limit = limit.unwrap_or(MAX_COHORT_BATCH);
+        require!(limit <= MAX_COHORT_BATCH, "ERR_BATCH_TOO_LARGE");
+        if !self.use_idempotency_key(idempotency_key) {
+            return;
+        }
+
+        let account_ids = self.holders_in_cohort(cohort.clone(), from, Some(limit));
+
+        if let Some((account_id, error)) = self
+            .validate_funding(account_ids.clone(), amount)
+            .into_iter()
+            .next()
+        {
+            require!(false, format!("{error}: {account_id}"));
+        }
+
+        for account_id in account_ids {
+            self.fund_participant(account_id, amount.into(), Some(cohort.clone()));
+        }
+    }
+
+    /// Withdraws NEAR that accumulated on the contract (e.g. deposits attached by
+    /// mistake) to the receiver or the owner, keeping enough to cover storage staking.
     #[payable]
-    pub fn fund_program_participant(&mut self, account_id: AccountId) {
+    pub fn withdraw_near(&mut self, amount: U128, receiver_id: Option<AccountId>) -> Promise {
         self.assert_owner();
         assert_one_yocto();
-        self.allowlist.insert(account_id.clone().into());
-        self.transfer(
+        let amount: u128 = amount.into();
+        let storage_reserve = u128::from(env::storage_usage()) * env::storage_byte_cost();
+        let available = env::account_balance().saturating_sub(storage_reserve);
+        require!(amount <= available, "ERR_INSUFFICIENT_AVAILABLE_BALANCE");
+
+        Promise::new(receiver_id.unwrap_or_else(|| self.own_get_owner().unwrap())).transfer(amount)
+    }
+
+    /// Checks whether each of the accounts could be funded with `amount` without
+    /// performing any transfers. Returns the problems found per account, an empty
+    /// list means that all of the accounts can be funded. Without an owner there is
+    /// nothing to fund from and every account is reported.
+    pub fn validate_funding(
+        &self,
+        account_ids: Vec<AccountId>,
+        amount: U128,
+    ) -> Vec<(AccountId, String)> {
+        let amount: u128 = amount.into();
+        let mut remaining: u128 = self.available_budget().into();
+        let mut seen = HashSet::new();
+        let mut problems = vec![];
+
+        for account_id in account_ids {
+            if !seen.insert(account_id.clone()) {
+                problems.push((account_id, "ERR_DUPLICATE_ACCOUNT".to_string()));
+                continue;
+            }
+
+            if !self.fits_daily_limit(&account_id, amount) {
+                problems.push((account_id, "ERR_DAILY_LIMIT_EXCEEDED".to_string()));
+                continue;
+            }
+
+            match remaining.checked_sub(amount) {
+                Some(balance) => remaining = balance,
+                None => problems.push((account_id, "ERR_INSUFFICIENT_OWNER_BALANCE".to_string())),
+            }
+        }
+
+        problems
+    }
+
+    /// The credits the owner can still award. Funding isn't reserved ahead of time,
+    /// so nothing is committed beyond what was already transferred and this is the
+    /// owner balance, or 0 when the contract has no owner.
+    pub fn available_budget(&self) -> U128 {
+        self.own_get_owner()
+            .map_or(0, |owner_id| self.ft_balance_of(owner_id).into())
+            .into()
+    }
+
+    /// Whether awards adding up to `total` fit in the [`Contract::available_budget`].
+    pub fn would_fund_fit(&self, total: U128) -> bool {
+        u128::from(total) <= u128::from(self.available_budget())
+    }
+
+    /// The contract configuration in a single call.
+    pub fn get_config(&self) -> ContractConfig {
+        let metadata = self.ft_metadata();
+
+        ContractConfig {
+            owner: self.own_get_owner(),
+            fund_amount: self.fund_amount.into(),
+            symbol: metadata.symbol,
+            decimals: metadata.decimals,
+            spending_restricted: self.spending_restricted,
+            max_daily_per_account: self.max_daily_per_account.map(U128),
+        }
+    }
+
+    /// The total amount of credits the account was ever funded with, regardless of
+    /// what it has spent or returned since.
+    pub fn total_awarded_to(&self, account_id: AccountId) -> U128 {
+        self.total_awarded
+            .get(&account_id)
+            .copied()
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Whether the account is registered on the allowlist, in any cohort or none.
+    pub fn on_allowlist(&self, account_id: AccountId) -> bool {
+        self.is_registered(&account_id)
+    }
+
+    /// Whether the account is a registered service provider.
+    pub fn is_provider(&self, account_id: AccountId) -> bool {
+        self.providers.contains(&account_id)
+    }
+
+    /// The cohort the account belongs to, if any.
+    pub fn cohort_of(&self, account_id: AccountId) -> Option<String> {
+        self.allowlist
+            .get(&account_id)
+            .and_then(|item| item.cohort())
+            .map(str::to_string)
+    }
+
+    /// The members of the cohort by position. Members are appended as they join, a
+    /// member leaving the cohort is replaced by the last one.
+    pub fn holders_in_cohort(
+        &self,
+        cohort: String,
+        from: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<AccountId> {
+        self.cohorts
+            .get(&cohort)
+            .map(|members| {
+                let from = from.unwrap_or_default().min(members.len());
+                let to = limit.map_or(members.len(), |limit| {
+                    from.saturating_add(limit).min(members.len())
+                });
+                (from..to)
+                    .filter_map(|index| members.get(index).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The number of members in the cohort.
+    pub fn cohort_size(&self, cohort: String) -> u32 {
+        self.cohorts.get(&cohort).map_or(0, |members| members.len())
+    }
+
+    /// Allowlist membership for each of the accounts, in the order they were provided.
+    pub fn on_allowlist_batch(&self, account_ids: Vec<AccountId>) -> Vec<bool> {
+        account_ids
+            .into_iter()
+            .map(|account_id| self.on_allowlist(account_id))
+            .collect()
+    }
+
+    /// The amount of credits held by everyone except the owner.
+    pub fn distributed_supply(&self) -> U128 {
+        let total_supply: u128 = self.ft_total_supply().into();
+        let owner_balance: u128 = self
+            .own_get_owner()
+            .map_or(0, |owner_id| self.ft_balance_of(owner_id).into());
+        total_supply.saturating_sub(owner_balance).into()
+    }
+
+    /// The combined balance of the provided accounts.
+    pub fn circulating_among_holders(&self, account_ids: Vec<AccountId>) -> U128 {
+        account_ids
+            .into_iter()
+            .map(|account_id| u128::from(self.ft_balance_of(account_id)))
+            .sum::<u128>()
+            .into()
+    }
+}
+
+impl Contract {
+    /// The state around the fields deployed before cohorts, with everything added
+    /// since empty.
+    fn with_legacy_state(state: ContractV0) -> Self {
+        Self {
+            legacy_allowlist: state.allowlist,
+            fund_amount: state.fund_amount,
+            total_awarded: LookupMap::new(b"t"),
+            idempotency_keys: LookupSet::new(b"i"),
+            allowlist: LookupMap::new(b"l"),
+            cohorts: LookupMap::new(b"m"),
+            cohort_positions: LookupMap::new(b"o"),
+            metadata: MutableMetadata::default(),
+            providers: LookupSet::new(b"p"),
+            spending_restricted: false,
+            daily_funding: LookupMap::new(b"d"),
+            max_daily_per_account: None,
+        }
+    }
+
+    /// Make sure the owner holds at least `amount` credits before funding.
+    fn require_owner_balance(&self, amount: u128) {
+        let owner_balance: u128 = self.ft_balance_of(self.own_get_owner().unwrap()).into();
+        require!(owner_balance >= amount, "ERR_INSUFFICIENT_OWNER_BALANCE");
+    }
+
+    /// Marks the key as used, returning `false` if it was used before. Calls without a
+    /// key are always allowed.
+    fn use_idempotency_key(&mut self, idempotency_key: Option<String>) -> bool {
+        match idempotency_key {
+            Some(key) if !self.idempotency_keys.insert(key.clone()) => {
+                env::log_str(&format!("Idempotency key {key} was already used, skipping"));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// The amount the account was funded with during the current UTC day.
+    fn funded_today(&self, account_id: &AccountId) -> u128 {
+        let today = env::block_timestamp() / DAY_NANOS;
+        match self.daily_funding.get(account_id) {
+            Some((day, amount)) if *day == today => *amount,
+            _ => 0,
+        }
+    }
+
+    /// Whether funding the account with `amount` keeps it within the daily cap.
+    fn fits_daily_limit(&self, account_id: &AccountId, amount: u128) -> bool {
+        self.max_daily_per_account.is_none_or(|max| {
+            self.funded_today(account_id)
+                .checked_add(amount)
+                .is_some_and(|total| total <= max)
+        })
+    }
+
+    /// Whether the account is on the allowlist or still on the legacy one.
+    fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.allowlist.contains_key(account_id)
+            || self.legacy_allowlist.contains(&account_id.clone().into())
+    }
+
+    /// Adds the account to the allowlist. Without a `cohort` the account keeps the
+    /// cohort it's already in, otherwise it's moved to the new one.
+    fn register(&mut self, account_id: AccountId, cohort: Option<String>) {
+        let previous = self.cohort_of(account_id.clone());
+        let cohort = cohort.or_else(|| previous.clone());
+
+        if previous != cohort {
+            if let Some(previous) = previous {
+                self.remove_from_cohort(&previous, &account_id);
+            }
+            if let Some(cohort) = &cohort {
+                let members = self
+                    .cohorts
+                    .entry(cohort.clone())
+                    .or_insert_with(|| Vector::new(cohort_members_prefix(cohort)));
+                self.cohort_positions
+                    .insert(account_id.clone(), members.len());
+                members.push(account_id.clone());
+            }
+        }
+
+        self.legacy_allowlist.remove(&account_id.clone().into());
+        self.allowlist.insert(
+            account_id.clone(),
+            VersionedAllowList::V1 { account_id, cohort },
+        );
+    }
+
+    /// Removes the account from the cohort's members, moving the last member into
+    /// its position.
+    fn remove_from_cohort(&mut self, cohort: &str, account_id: &AccountId) {
+        let Some(position) = self.cohort_positions.remove(account_id) else {
+            return;
+        };
+        let Some(members) = self.cohorts.get_mut(cohort) else {
+            return;
+        };
+
+        members.swap_remove(position);
+        if let Some(moved) = members.get(position) {
+            self.cohort_positions.insert(moved.clone(), position);
+        }
+    }
+
+    /// Registers the participant and transfers them `amount`.
+    fn fund_participant(&mut self, account_id: AccountId, amount: u128, memo: Option<String>) {
+        require!(
+            self.fits_daily_limit(&account_id, amount),
+            "ERR_DAILY_LIMIT_EXCEEDED"
+        );
+        let funded_today = self.funded_today(&account_id);
+        self.daily_funding.insert(
+            account_id.clone(),
+            (env::block_timestamp() / DAY_NANOS, funded_today + amount),
+        );
+        self.register(account_id.clone(), None);
+        *self.total_awarded.entry(account_id.clone()).or_default() += amount;
+        self.privileged_transfer(
             self.own_get_owner().unwrap(),
+            account_id.clone(),
+            amount,
+            Some(
+                memo.clone()
+                    .unwrap_or_else(|| "Awarding credits to program participant".to_string()),
+            ),
+        );
+        Events::ParticipantFunded {
             account_id,
-            self.fund_amount,
-            Some("Awarding credits to program participant".to_string()),
+            amount: amount.into(),
+            program: memo,
+        }
+        .emit();
+    }
+
+    /// Moves credits between the owner and a program participant. Unlike `ft_transfer`
+    /// only the participant has to be on the allowlist, the owner side is trusted so
+    /// treasury moves keep working even if the owner is no longer registered.
+    fn privileged_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: u128,
+        memo: Option<String>,
+    ) {
+        let owner_id = self.own_get_owner().unwrap();
+        let participant_id = if sender_id == owner_id {
+            &receiver_id
+        } else {
+            require!(receiver_id == owner_id, "ERR_NOT_PRIVILEGED_TRANSFER");
+            &sender_id
+        };
+        require!(
+            self.is_registered(participant_id),
+            "ERR_PARTICIPANT_NOT_REGISTERED"
         );
+
+        self.transfer(sender_id, receiver_id, amount, memo);
+    }
+}
+
+#[near_bindgen]
+impl Nep148 for Contract {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.into(),
+            name: self.metadata.name.clone(),
+            symbol: self.metadata.symbol.clone(),
+            icon: None,
+            reference: self.metadata.reference.clone(),
+            reference_hash: None,
+            decimals: DECIMALS,
+        }
     }
 }
 
 impl Nep141Hook for Contract {
     fn before_transfer(&mut self, transfer: &Nep141Transfer) {
         require!(
-            self.allowlist.contains(&transfer.sender_id.clone().into()),
+            self.is_registered(&transfer.sender_id),
             "ERR_SENDER_NOT_REGISTERED"
         );
         require!(
-            self.allowlist
-                .contains(&transfer.receiver_id.clone().into()),
+            self.is_registered(&transfer.receiver_id),
             "ERR_RECEIVER_NOT_REGISTERED"
-        )
+        );
+
+        if self.spending_restricted {
+            let owner_id = self.own_get_owner();
+            require!(
+                owner_id.as_ref() == Some(&transfer.sender_id)
+                    || owner_id.as_ref() == Some(&transfer.receiver_id)
+                    || self.providers.contains(&transfer.receiver_id),
+                "ERR_RECEIVER_NOT_PROVIDER"
+            );
+        }
     }
 
+    // The `FtTransfer` event is already emitted by `Nep141Controller::transfer` (for
+    // both `ft_transfer` and internal transfers), emitting it here would log it twice
     fn after_transfer(&mut self, _transfer: &Nep141Transfer, _state: ()) {}
 }
 
 #[cfg(test)]
 mod tests {
-    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+    use near_sdk::mock::VmAction;
+    use near_sdk::{
+        test_utils::{get_created_receipts, get_logs, VMContextBuilder},
+        testing_env, VMContext,
+    };
     use near_sdk_contract_tools::{owner::OwnerExternal, standard::nep141::Nep141};
 
     use super::*;
@@ -228,7 +841,7 @@ mod tests {
         let bob: AccountId = "bob.near".parse().unwrap();
         let alice: AccountId = "alice.near".parse().unwrap();
         let total_supply = 1_000_000;
-        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
 
         let context = VMContextBuilder::new()
             .predecessor_account_id(bob.clone())
@@ -237,10 +850,970 @@ mod tests {
 
         testing_env!(context);
 
-        contract.fund_program_participant(alice.clone());
+        contract.fund_program_participant(alice.clone(), None, None);
 
         assert_eq!(contract.ft_balance_of(alice), 50_000.into());
         assert_eq!(contract.ft_balance_of(bob), (total_supply - 50_000).into());
         assert_eq!(contract.ft_total_supply(), total_supply.into());
     }
+
+    #[test]
+    fn test_distributed_supply() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
+
+        assert_eq!(contract.distributed_supply(), 0.into());
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+        contract.fund_program_participant(carol.clone(), None, None);
+
+        assert_eq!(contract.distributed_supply(), 100_000.into());
+        assert_eq!(
+            contract.circulating_among_holders(vec![alice.clone(), carol]),
+            100_000.into()
+        );
+        assert_eq!(
+            contract.circulating_among_holders(vec![alice, bob]),
+            (total_supply - 50_000).into()
+        );
+    }
+
+    #[test]
+    fn test_distributed_supply_without_owner() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.own_renounce_owner();
+
+        assert_eq!(contract.own_get_owner(), None);
+        assert_eq!(contract.distributed_supply(), total_supply.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INSUFFICIENT_OWNER_BALANCE")]
+    fn test_fund_program_participant_insufficient_balance() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let total_supply = 10_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice, None, None);
+    }
+
+    #[test]
+    fn test_fund_program_participant_with_memo() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice, Some("Cohort 3 award".to_string()), None);
+
+        assert!(get_logs().iter().any(|log| log.starts_with("EVENT_JSON:")
+            && log.contains("\"ft_transfer\"")
+            && log.contains("\"memo\":\"Cohort 3 award\"")));
+    }
+
+    #[test]
+    fn test_validate_funding() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let total_supply = 100_000;
+        let contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        assert!(contract
+            .validate_funding(vec![alice.clone(), carol.clone()], 50_000.into())
+            .is_empty());
+
+        let problems = contract.validate_funding(
+            vec![alice.clone(), carol.clone(), alice.clone(), dave.clone()],
+            50_000.into(),
+        );
+
+        assert_eq!(
+            problems,
+            vec![
+                (alice.clone(), "ERR_DUPLICATE_ACCOUNT".to_string()),
+                (dave, "ERR_INSUFFICIENT_OWNER_BALANCE".to_string()),
+            ]
+        );
+        assert_eq!(contract.ft_balance_of(bob), total_supply.into());
+        assert_eq!(contract.ft_balance_of(alice), 0.into());
+    }
+
+    #[test]
+    fn test_validate_funding_without_owner() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 100_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.own_renounce_owner();
+
+        assert_eq!(
+            contract.validate_funding(vec![alice.clone()], 50_000.into()),
+            vec![(alice, "ERR_INSUFFICIENT_OWNER_BALANCE".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_on_allowlist_batch() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.register_holder(alice.clone());
+
+        assert_eq!(
+            contract.on_allowlist_batch(vec![carol.clone(), bob, carol, alice]),
+            vec![false, true, false, true]
+        );
+    }
+
+    fn withdraw_context(owner: &AccountId) -> VMContext {
+        VMContextBuilder::new()
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(1)
+            .account_balance(10_u128.pow(24))
+            .storage_usage(1_000)
+            .build()
+    }
+
+    #[test]
+    fn test_withdraw_near() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        testing_env!(withdraw_context(&bob));
+
+        let _ = contract.withdraw_near(10_u128.pow(23).into(), Some(alice.clone()));
+        let _ = contract.withdraw_near(10_u128.pow(23).into(), None);
+
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0].receiver_id, alice);
+        assert_eq!(
+            receipts[0].actions,
+            vec![VmAction::Transfer {
+                deposit: 10_u128.pow(23)
+            }]
+        );
+        assert_eq!(receipts[1].receiver_id, bob);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INSUFFICIENT_AVAILABLE_BALANCE")]
+    fn test_withdraw_near_keeps_storage_reserve() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        testing_env!(withdraw_context(&bob));
+
+        // 1_000 bytes of storage need 10^22 yoctoNEAR to stay on the contract, the
+        // attached yoctoNEAR is part of the balance
+        let available = 10_u128.pow(24) + 1 - 10_u128.pow(22);
+        let _ = contract.withdraw_near((available + 1).into(), None);
+    }
+
+    #[test]
+    fn test_fund_program_participant_events() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice, Some("Cohort 3".to_string()), None);
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        assert!(logs[0].starts_with("EVENT_JSON:") && logs[0].contains("\"ft_transfer\""));
+        assert!(
+            logs[1].starts_with("EVENT_JSON:")
+                && logs[1].contains("\"participant_funded\"")
+                && logs[1].contains("\"account_id\":\"alice.near\"")
+                && logs[1].contains("\"amount\":\"50000\"")
+                && logs[1].contains("\"program\":\"Cohort 3\"")
+        );
+    }
+
+    #[test]
+    fn test_fund_program_participant_without_registered_owner() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
+        contract.allowlist.remove(&bob);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        assert_eq!(contract.ft_balance_of(alice), 50_000.into());
+        assert_eq!(contract.ft_balance_of(bob), (total_supply - 50_000).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_NOT_REGISTERED")]
+    fn test_transfer_requires_registered_receiver() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(carol, 1_000.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_SENDER_NOT_REGISTERED")]
+    fn test_transfer_requires_registered_sender() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(carol)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(alice, 1_000.into(), None);
+    }
+
+    #[test]
+    fn test_get_config() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(20_000.into()));
+
+        assert_eq!(
+            contract.get_config(),
+            ContractConfig {
+                owner: Some(bob.clone()),
+                fund_amount: 20_000.into(),
+                symbol: "NHZN".to_string(),
+                decimals: 4,
+                spending_restricted: false,
+                max_daily_per_account: None,
+            }
+        );
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+        contract.own_propose_owner(Some(alice.clone()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.own_accept_owner();
+
+        let config = contract.get_config();
+        assert_eq!(config.owner, Some(alice));
+        assert_eq!(config.fund_amount, 20_000.into());
+    }
+
+    #[test]
+    fn test_fund_program_participants() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participants(vec![alice.clone(), carol.clone()], None, None);
+
+        assert_eq!(contract.ft_balance_of(alice), 50_000.into());
+        assert_eq!(contract.ft_balance_of(carol), 50_000.into());
+        assert_eq!(contract.ft_balance_of(bob), (total_supply - 100_000).into());
+    }
+
+    #[test]
+    fn test_fund_program_participants_is_atomic() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let total_supply = 100_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.fund_program_participants(
+                vec![alice.clone(), carol.clone(), dave],
+                None,
+                None,
+            );
+        }));
+
+        // Only the last account doesn't fit, yet nobody is registered or funded
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<String>().map(String::as_str),
+            Some("ERR_INSUFFICIENT_OWNER_BALANCE: dave.near")
+        );
+        assert!(!contract.on_allowlist(alice.clone()));
+        assert!(!contract.on_allowlist(carol.clone()));
+        assert_eq!(contract.ft_balance_of(alice), 0.into());
+        assert_eq!(contract.ft_balance_of(carol), 0.into());
+        assert_eq!(contract.ft_balance_of(bob), total_supply.into());
+    }
+
+    #[test]
+    fn test_total_awarded_to() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(50_000.into()));
+
+        assert_eq!(contract.total_awarded_to(alice.clone()), 0.into());
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, None);
+        contract.fund_program_participants(vec![alice.clone()], None, None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.claim_credits();
+
+        assert_eq!(contract.ft_balance_of(alice.clone()), 0.into());
+        assert_eq!(contract.total_awarded_to(alice), 100_000.into());
+    }
+
+    #[test]
+    fn test_fund_program_participant_idempotency_key() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(50_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice.clone(), None, Some("award-1".to_string()));
+        contract.fund_program_participant(alice.clone(), None, Some("award-1".to_string()));
+
+        assert_eq!(contract.ft_balance_of(alice.clone()), 50_000.into());
+
+        contract.fund_program_participant(alice.clone(), None, Some("award-2".to_string()));
+
+        assert_eq!(contract.ft_balance_of(alice), 100_000.into());
+
+        contract.fund_program_participants(vec![carol.clone()], None, Some("award-2".to_string()));
+
+        assert_eq!(contract.ft_balance_of(carol), 0.into());
+    }
+
+    fn ft_transfer_event_count() -> usize {
+        get_logs()
+            .iter()
+            .filter(|log| log.starts_with("EVENT_JSON:") && log.contains("\"ft_transfer\""))
+            .count()
+    }
+
+    #[test]
+    fn test_transfer_emits_single_event() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context.clone());
+
+        contract.register_holder(alice.clone());
+        contract.transfer(bob, alice.clone(), 1_000, Some("Internal".to_string()));
+
+        assert_eq!(ft_transfer_event_count(), 1);
+        assert!(get_logs()
+            .iter()
+            .any(|log| log.contains("\"old_owner_id\":\"bob.near\"")
+                && log.contains("\"new_owner_id\":\"alice.near\"")
+                && log.contains("\"amount\":\"1000\"")
+                && log.contains("\"memo\":\"Internal\"")));
+
+        testing_env!(context);
+
+        contract.ft_transfer(alice, 1_000.into(), None);
+
+        assert_eq!(ft_transfer_event_count(), 1);
+    }
+
+    #[test]
+    fn test_fund_cohort() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.register_holder_in_cohort(alice.clone(), "cohort-1".to_string());
+        contract.register_holder_in_cohort(carol.clone(), "cohort-1".to_string());
+        contract.register_holder_in_cohort(dave.clone(), "cohort-2".to_string());
+
+        assert_eq!(
+            contract.holders_in_cohort("cohort-1".to_string(), None, None),
+            vec![alice.clone(), carol.clone()]
+        );
+        assert_eq!(
+            contract.holders_in_cohort("cohort-1".to_string(), Some(1), Some(1)),
+            vec![carol.clone()]
+        );
+
+        contract.fund_cohort("cohort-1".to_string(), 10_000.into(), None, None, None);
+
+        assert_eq!(contract.ft_balance_of(alice.clone()), 10_000.into());
+        assert_eq!(contract.ft_balance_of(carol.clone()), 10_000.into());
+        assert_eq!(contract.ft_balance_of(dave.clone()), 0.into());
+        assert_eq!(contract.ft_balance_of(bob), (total_supply - 20_000).into());
+
+        // Funding doesn't change the cohort, registering in another one moves the account
+        assert_eq!(
+            contract.cohort_of(alice.clone()),
+            Some("cohort-1".to_string())
+        );
+        contract.register_holder_in_cohort(alice.clone(), "cohort-2".to_string());
+        contract.register_holder(alice.clone());
+
+        assert_eq!(
+            contract.holders_in_cohort("cohort-1".to_string(), None, None),
+            vec![carol]
+        );
+        assert_eq!(
+            contract.holders_in_cohort("cohort-2".to_string(), None, None),
+            vec![dave, alice]
+        );
+    }
+
+    #[test]
+    fn test_fund_cohort_in_ranges() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let total_supply = 1_000_000;
+        let mut contract = Contract::new(bob.clone(), total_supply.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        let members: Vec<AccountId> = (0..5)
+            .map(|i| format!("member-{i}.near").parse().unwrap())
+            .collect();
+        for member in &members {
+            contract.register_holder_in_cohort(member.clone(), "cohort-1".to_string());
+        }
+        assert_eq!(contract.cohort_size("cohort-1".to_string()), 5);
+
+        // Removing a member moves the last one into its position
+        contract.register_holder_in_cohort(members[1].clone(), "cohort-2".to_string());
+        assert_eq!(contract.cohort_size("cohort-1".to_string()), 4);
+        assert_eq!(
+            contract.holders_in_cohort("cohort-1".to_string(), None, None),
+            vec![
+                members[0].clone(),
+                members[4].clone(),
+                members[2].clone(),
+                members[3].clone()
+            ]
+        );
+
+        contract.fund_cohort("cohort-1".to_string(), 1_000.into(), None, Some(3), None);
+        contract.fund_cohort("cohort-1".to_string(), 1_000.into(), Some(3), Some(3), None);
+
+        for member in [&members[0], &members[2], &members[3], &members[4]] {
+            assert_eq!(contract.ft_balance_of(member.clone()), 1_000.into());
+        }
+        assert_eq!(contract.ft_balance_of(members[1].clone()), 0.into());
+        assert_eq!(contract.ft_balance_of(bob), (total_supply - 4_000).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BATCH_TOO_LARGE")]
+    fn test_fund_cohort_batch_limit() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_cohort(
+            "cohort-1".to_string(),
+            1_000.into(),
+            None,
+            Some(MAX_COHORT_BATCH + 1),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_migrate() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+
+        let mut allowlist = LookupSet::new(b"a");
+        allowlist.insert(VersionedAllowList::from(alice.clone()));
+        env::state_write(&ContractV0 {
+            allowlist,
+            fund_amount: 20_000,
+        });
+
+        let mut contract = Contract::migrate();
+        // The owner is kept in its own storage, which the migration doesn't touch
+        Owner::init(&mut contract, &bob);
+
+        assert!(contract.on_allowlist(alice.clone()));
+        assert_eq!(contract.get_config().fund_amount, 20_000.into());
+        assert_eq!(contract.ft_metadata().symbol, "NHZN");
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.migrate_allowlist(vec![alice.clone()]);
+        contract.register_holder_in_cohort(alice.clone(), "cohort-1".to_string());
+
+        // The migrated state round trips, once the collections are flushed
+        env::state_write(&contract);
+        drop(contract);
+        let contract: Contract = env::state_read().unwrap();
+
+        assert!(contract.on_allowlist(alice.clone()));
+        assert_eq!(contract.get_config().fund_amount, 20_000.into());
+        assert_eq!(
+            contract.cohort_of(alice.clone()),
+            Some("cohort-1".to_string())
+        );
+        assert_eq!(
+            contract.holders_in_cohort("cohort-1".to_string(), None, None),
+            vec![alice]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_STATE")]
+    fn test_migrate_without_state() {
+        Contract::migrate();
+    }
+
+    #[test]
+    fn test_migrate_allowlist() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+        contract.legacy_allowlist.insert(alice.clone().into());
+
+        assert!(contract.on_allowlist(alice.clone()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.migrate_allowlist(vec![alice.clone(), carol.clone()]);
+
+        assert!(contract.on_allowlist(alice.clone()));
+        assert!(!contract.on_allowlist(carol));
+        assert!(!contract.legacy_allowlist.contains(&alice.clone().into()));
+        assert_eq!(contract.cohort_of(alice), None);
+    }
+
+    #[test]
+    fn test_set_metadata() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.set_metadata(None, Some("HZN".to_string()), None);
+
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.name, "NEAR Horizon");
+        assert_eq!(metadata.symbol, "HZN");
+        assert_eq!(metadata.decimals, 4);
+        assert_eq!(contract.get_config().symbol, "HZN");
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_EMPTY_SYMBOL")]
+    fn test_set_metadata_requires_symbol() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.set_metadata(None, Some(" ".to_string()), None);
+    }
+
+    #[test]
+    fn test_register_holder_events() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), None);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.register_holder(alice);
+        contract.register_holder_in_cohort(carol, "cohort-1".to_string());
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 2);
+        assert!(
+            logs[0].starts_with("EVENT_JSON:")
+                && logs[0].contains("\"holder_registered\"")
+                && logs[0].contains("\"account_id\":\"alice.near\"")
+                && logs[0].contains("\"cohort\":null")
+        );
+        assert!(
+            logs[1].contains("\"holder_registered\"")
+                && logs[1].contains("\"account_id\":\"carol.near\"")
+                && logs[1].contains("\"cohort\":\"cohort-1\"")
+        );
+    }
+
+    /// A contract with spending restricted, `alice` and `carol` funded as
+    /// participants and `dave` registered as a provider.
+    fn restricted_contract(bob: &AccountId) -> Contract {
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(10_000.into()));
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice, None, None);
+        contract.fund_program_participant(carol, None, None);
+        contract.register_provider(dave);
+        contract.set_spending_restricted(true);
+
+        contract
+    }
+
+    #[test]
+    fn test_participant_pays_provider() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let dave: AccountId = "dave.near".parse().unwrap();
+        let mut contract = restricted_contract(&bob);
+
+        assert!(contract.is_provider(dave.clone()));
+        assert!(!contract.is_provider(alice.clone()));
+        assert!(contract.get_config().spending_restricted);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(dave.clone(), 4_000.into(), None);
+
+        // The provider settles with the owner
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(dave.clone())
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(bob.clone(), 1_000.into(), None);
+
+        assert_eq!(contract.ft_balance_of(alice), 6_000.into());
+        assert_eq!(contract.ft_balance_of(dave), 3_000.into());
+        assert_eq!(
+            contract.ft_balance_of(bob),
+            (1_000_000 - 20_000 + 1_000).into()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_RECEIVER_NOT_PROVIDER")]
+    fn test_participant_cant_pay_participant() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = restricted_contract(&bob);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(carol, 1_000.into(), None);
+    }
+
+    #[test]
+    fn test_unrestricted_participants_transfer_freely() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = restricted_contract(&bob);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.set_spending_restricted(false);
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(alice)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.ft_transfer(carol.clone(), 1_000.into(), None);
+
+        assert_eq!(contract.ft_balance_of(carol), 11_000.into());
+    }
+
+    #[test]
+    fn test_available_budget() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(20_000.into()));
+
+        assert_eq!(contract.available_budget(), 1_000_000.into());
+
+        let context = VMContextBuilder::new()
+            .predecessor_account_id(bob)
+            .attached_deposit(1)
+            .build();
+
+        testing_env!(context);
+
+        contract.fund_program_participant(alice, None, None);
+        contract.fund_program_participant(carol, None, None);
+
+        assert_eq!(contract.available_budget(), 960_000.into());
+        assert!(contract.would_fund_fit(960_000.into()));
+        assert!(!contract.would_fund_fit(960_001.into()));
+    }
+
+    fn funding_context(owner: &AccountId, block_timestamp: u64) -> VMContext {
+        VMContextBuilder::new()
+            .predecessor_account_id(owner.clone())
+            .attached_deposit(1)
+            .block_timestamp(block_timestamp)
+            .build()
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DAILY_LIMIT_EXCEEDED")]
+    fn test_daily_limit_same_day() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(20_000.into()));
+
+        testing_env!(funding_context(&bob, 10 * DAY_NANOS + 1));
+
+        contract.set_max_daily_per_account(Some(30_000.into()));
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        // Still the same UTC day
+        testing_env!(funding_context(&bob, 11 * DAY_NANOS - 1));
+
+        contract.fund_program_participant(alice, None, None);
+    }
+
+    #[test]
+    fn test_daily_limit_resets_next_day() {
+        let bob: AccountId = "bob.near".parse().unwrap();
+        let alice: AccountId = "alice.near".parse().unwrap();
+        let carol: AccountId = "carol.near".parse().unwrap();
+        let mut contract = Contract::new(bob.clone(), 1_000_000.into(), Some(20_000.into()));
+
+        testing_env!(funding_context(&bob, 10 * DAY_NANOS + 1));
+
+        contract.set_max_daily_per_account(Some(30_000.into()));
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        assert_eq!(
+            contract.validate_funding(vec![alice.clone(), carol.clone()], 20_000.into()),
+            vec![(alice.clone(), "ERR_DAILY_LIMIT_EXCEEDED".to_string())]
+        );
+
+        testing_env!(funding_context(&bob, 11 * DAY_NANOS));
+
+        contract.fund_program_participant(alice.clone(), None, None);
+
+        assert_eq!(contract.ft_balance_of(alice), 40_000.into());
+        assert_eq!(
+            contract.get_config().max_daily_per_account,
+            Some(30_000.into())
+        );
+    }
 }